@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use math::{Vec2, V2_ORIGIN};
+use world::{World, SectorIndex, WallIndex};
+use input::{InputState, Key};
+use settings::OverlaySettings;
+
+
+const DEFAULT_SCALE: f32 = 2.0;
+pub const MIN_SCALE: f32 = 0.5;
+pub const MAX_SCALE: f32 = 16.0;
+// Zooming multiplies the scale by e^ZOOM_RATE per second, panning is in pixels per second.
+const ZOOM_RATE: f32 = 1.5;
+const PAN_SPEED: f32 = 120.0;
+
+
+// Where the map is drawn over the 3D view while the full map isn't shown.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum OverlayMode {
+    Off,
+    Corner,
+    Full,
+}
+
+// The view of the overhead map. `scale` is in pixels per world unit and `pan` is how far the
+// view is moved from the player, in world units along the screen's axes.
+pub struct Automap {
+    pub scale: f32,
+    pub pan: Vec2,
+    pub seen_only: bool,
+    pub overlay: OverlaySettings,
+    _seen: Vec<Vec<AtomicBool>>,
+}
+
+
+impl OverlayMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            OverlayMode::Off    => "off",
+            OverlayMode::Corner => "corner",
+            OverlayMode::Full   => "full",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<OverlayMode> {
+        [OverlayMode::Off, OverlayMode::Corner, OverlayMode::Full].iter().cloned().find(|mode| mode.name() == name)
+    }
+
+    pub fn next(self) -> OverlayMode {
+        match self {
+            OverlayMode::Off    => OverlayMode::Corner,
+            OverlayMode::Corner => OverlayMode::Full,
+            OverlayMode::Full   => OverlayMode::Off,
+        }
+    }
+}
+
+
+impl Automap {
+    pub fn new(world: &World) -> Automap {
+        Automap {
+            scale: DEFAULT_SCALE,
+            pan: V2_ORIGIN,
+            seen_only: false,
+            overlay: OverlaySettings::default(),
+            _seen: world.get_sectors().iter()
+                .map(|sector| sector.walls.iter().map(|_| AtomicBool::new(false)).collect())
+                .collect(),
+        }
+    }
+
+    // Called by the renderer for every wall a ray hits, which may happen from several render
+    // threads at once.
+    pub fn mark_seen(&self, sector: SectorIndex, wall: WallIndex) {
+        let (SectorIndex(sector), WallIndex(wall)) = (sector, wall);
+        self._seen[sector][wall].store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_seen(&self, sector: SectorIndex, wall: WallIndex) -> bool {
+        let (SectorIndex(sector), WallIndex(wall)) = (sector, wall);
+        self._seen[sector][wall].load(Ordering::Relaxed)
+    }
+
+    pub fn reset_view(&mut self) {
+        self.pan = V2_ORIGIN;
+    }
+
+    pub fn step(&mut self, dt: f32, input: &InputState) {
+        if input.has_key(Key::MapZoomIn)  { self.scale *= (ZOOM_RATE * dt).exp(); }
+        if input.has_key(Key::MapZoomOut) { self.scale /= (ZOOM_RATE * dt).exp(); }
+        self.scale = self.scale.min(MAX_SCALE).max(MIN_SCALE);
+
+        let pan = PAN_SPEED * dt / self.scale;
+        if input.has_key(Key::MapPanUp)    { self.pan.y -= pan; }
+        if input.has_key(Key::MapPanDown)  { self.pan.y += pan; }
+        if input.has_key(Key::MapPanLeft)  { self.pan.x -= pan; }
+        if input.has_key(Key::MapPanRight) { self.pan.x += pan; }
+    }
+}
@@ -0,0 +1,134 @@
+use math::{LineSeg, Vec2};
+use world::{World, Sector, SectorInfo, SectorIndex, Wall, WallIndex};
+use texture::TextureId;
+
+
+const EPSILON: f32 = 1.0e-3;
+
+
+// Builds a world from plain polygons and links portals between sectors automatically.
+// Every loop should be wound so that an edge shared by two sectors runs in opposite
+// directions on either side, i.e. all outer boundaries wound one way and holes the other.
+pub struct WorldBuilder {
+    _sectors: Vec<(SectorInfo, Vec<(LineSeg, TextureId)>)>,
+    _textures: Vec<String>,
+}
+
+
+impl WorldBuilder {
+    pub fn new() -> WorldBuilder {
+        WorldBuilder {
+            _sectors: vec![],
+            _textures: vec![],
+        }
+    }
+
+    pub fn add_texture(&mut self, name: &str) -> TextureId {
+        match self._textures.iter().position(|t| t == name) {
+            Some(index) => TextureId(index),
+            None => {
+                self._textures.push(name.to_string());
+                TextureId(self._textures.len() - 1)
+            }
+        }
+    }
+
+    pub fn add_sector(&mut self, info: SectorInfo, texture: TextureId, polygon: &[Vec2]) -> SectorIndex {
+        self._sectors.push((info, vec![]));
+        let sector = SectorIndex(self._sectors.len() - 1);
+        self.add_loop(sector, texture, polygon);
+        sector
+    }
+
+    pub fn add_loop(&mut self, sector: SectorIndex, texture: TextureId, polygon: &[Vec2]) {
+        let SectorIndex(index) = sector;
+        let edges = &mut self._sectors[index].1;
+
+        for i in 0..polygon.len() {
+            let a = polygon[i];
+            let b = polygon[(i + 1) % polygon.len()];
+            edges.push((LineSeg { a: a, b: b }, texture));
+        }
+    }
+
+    pub fn build(self) -> World {
+        let split_edges: Vec<Vec<(LineSeg, TextureId)>> = self._sectors.iter().enumerate()
+            .map(|(index, &(_, ref edges))| {
+                edges.iter()
+                    .flat_map(|&(edge, texture)| {
+                        self.split_edge(index, edge).into_iter().map(move |seg| (seg, texture))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut sectors: Vec<Sector> = self._sectors.iter().zip(split_edges.iter())
+            .map(|(&(info, _), edges)| Sector {
+                info: info,
+                walls: edges.iter().map(|&(seg, texture)| Wall::from_seg(seg, texture, None)).collect(),
+            })
+            .collect();
+
+        for (sec_index, edges) in split_edges.iter().enumerate() {
+            for (wall_index, &(edge, _)) in edges.iter().enumerate() {
+                sectors[sec_index].walls[wall_index].portal = split_edges.iter().enumerate()
+                    .filter(|&(other_sec, _)| other_sec != sec_index)
+                    .flat_map(|(other_sec, other_edges)| {
+                        other_edges.iter().enumerate()
+                            .filter(|&(_, &(other, _))| edge.a.is_near(other.b, EPSILON) && edge.b.is_near(other.a, EPSILON))
+                            .map(move |(other_wall, _)| (SectorIndex(other_sec), WallIndex(other_wall)))
+                    })
+                    .next();
+            }
+        }
+
+        World::new(sectors, self._textures)
+    }
+
+    // Splits an edge at every end point of an opposite-facing collinear edge in another
+    // sector which lands strictly inside it, so that partially overlapping edges end up
+    // as sub-segments with matching end points on both sides.
+    fn split_edge(&self, sector: usize, edge: LineSeg) -> Vec<LineSeg> {
+        let dir = edge.b - edge.a;
+        let len_sqr = dir.get_length_sqr();
+        let len = len_sqr.sqrt();
+
+        if len < EPSILON {
+            return vec![edge];
+        }
+
+        let mut cuts: Vec<(f32, Vec2)> = vec![];
+
+        for (other_sec, &(_, ref other_edges)) in self._sectors.iter().enumerate() {
+            if other_sec == sector { continue; }
+
+            for &(other, _) in other_edges.iter() {
+                let other_dir = other.b - other.a;
+                if dir.dot(other_dir) >= 0.0 { continue; }
+
+                let collinear = (other.a - edge.a).cross(dir).abs() / len < EPSILON
+                             && (other.b - edge.a).cross(dir).abs() / len < EPSILON;
+                if !collinear { continue; }
+
+                for &pt in [other.a, other.b].iter() {
+                    let t = (pt - edge.a).dot(dir) / len_sqr;
+                    if t * len > EPSILON && (1.0 - t) * len > EPSILON {
+                        cuts.push((t, pt));
+                    }
+                }
+            }
+        }
+
+        cuts.sort_by(|&(t0, _), &(t1, _)| t0.partial_cmp(&t1).unwrap());
+        cuts.dedup_by(|&mut (t0, _), &mut (t1, _)| (t0 - t1).abs() * len < EPSILON);
+
+        let mut result = vec![];
+        let mut start = edge.a;
+        for &(_, pt) in cuts.iter() {
+            result.push(LineSeg { a: start, b: pt });
+            start = pt;
+        }
+        result.push(LineSeg { a: start, b: edge.b });
+        result
+    }
+}
@@ -0,0 +1,84 @@
+// Tests for `WorldBuilder`, which splits overlapping edges and links portals between them.
+
+use builder::WorldBuilder;
+use math::{LineSeg, Vec2};
+use test_support::{EPSILON, info, rect};
+use world::{World, SectorIndex, WallIndex};
+
+
+fn build(rects: &[(f32, f32, f32, f32)]) -> World {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    for &(x0, y0, x1, y1) in rects.iter() {
+        builder.add_sector(info(0.0, 20.0), texture, &rect(x0, y0, x1, y1));
+    }
+    let world = builder.build();
+    assert_eq!(world.validate(), vec![]);
+    world
+}
+
+// Every portal in `sector` as its segment and the sector it leads to.
+fn portals(world: &World, sector: usize) -> Vec<(LineSeg, usize)> {
+    world.get_sectors()[sector].walls.iter()
+        .filter_map(|wall| wall.portal.map(|(SectorIndex(target), _)| (wall.seg, target)))
+        .collect()
+}
+
+fn assert_seg(seg: LineSeg, x0: f32, y0: f32, x1: f32, y1: f32) {
+    assert!(seg.a.is_near(Vec2::new(x0, y0), EPSILON) && seg.b.is_near(Vec2::new(x1, y1), EPSILON),
+        "expected ({}, {}) to ({}, {}), got ({}, {}) to ({}, {})", x0, y0, x1, y1, seg.a.x, seg.a.y, seg.b.x, seg.b.y);
+}
+
+
+#[test]
+fn shared_edge_becomes_a_portal() {
+    let world = build(&[(0.0, 0.0, 10.0, 10.0), (10.0, 0.0, 20.0, 10.0)]);
+
+    assert_eq!(world.get_sectors()[0].walls.len(), 4);
+    assert_eq!(world.get_sectors()[0].walls[1].portal, Some((SectorIndex(1), WallIndex(3))));
+    assert_eq!(world.get_sectors()[1].walls[3].portal, Some((SectorIndex(0), WallIndex(1))));
+}
+
+#[test]
+fn separate_sectors_have_no_portals() {
+    let world = build(&[(0.0, 0.0, 10.0, 10.0), (11.0, 0.0, 21.0, 10.0)]);
+
+    assert!(portals(&world, 0).is_empty());
+    assert!(portals(&world, 1).is_empty());
+}
+
+#[test]
+fn partially_overlapping_edges_are_split() {
+    // The right sector is shifted down by half, so only the lower half of the left one's east
+    // edge is shared.
+    let world = build(&[(0.0, 0.0, 10.0, 10.0), (10.0, 5.0, 20.0, 15.0)]);
+
+    assert_eq!(world.get_sectors()[0].walls.len(), 5);
+    assert_eq!(world.get_sectors()[1].walls.len(), 5);
+
+    let left = portals(&world, 0);
+    let right = portals(&world, 1);
+    assert_eq!(left.len(), 1);
+    assert_eq!(right.len(), 1);
+    assert_seg(left[0].0, 10.0, 5.0, 10.0, 10.0);
+    assert_seg(right[0].0, 10.0, 10.0, 10.0, 5.0);
+    assert_eq!((left[0].1, right[0].1), (1, 0));
+}
+
+#[test]
+fn t_junction_splits_the_long_edge() {
+    // One tall sector with two stacked squares against its east edge.
+    let world = build(&[(0.0, 0.0, 10.0, 20.0), (10.0, 0.0, 20.0, 10.0), (10.0, 10.0, 20.0, 20.0)]);
+
+    let left = portals(&world, 0);
+    assert_eq!(left.len(), 2);
+    assert_seg(left[0].0, 10.0, 0.0, 10.0, 10.0);
+    assert_seg(left[1].0, 10.0, 10.0, 10.0, 20.0);
+    assert_eq!((left[0].1, left[1].1), (1, 2));
+
+    // The squares also share an edge with each other.
+    let lower: Vec<usize> = portals(&world, 1).iter().map(|&(_, target)| target).collect();
+    let upper: Vec<usize> = portals(&world, 2).iter().map(|&(_, target)| target).collect();
+    assert_eq!(lower, vec![2, 0]);
+    assert_eq!(upper, vec![1, 0]);
+}
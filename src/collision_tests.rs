@@ -0,0 +1,166 @@
+// Tests for `World::move_object`, which keeps a round collider out of solid walls and slides
+// it along them, and for `World::can_enter`, which decides which portals it fits through.
+
+use builder::WorldBuilder;
+use math::{LineSeg, Vec2};
+use test_support::{EPSILON, info, rect, assert_near};
+use world::{World, SectorIndex, Collider};
+
+
+const COLLIDER: Collider = Collider {
+    radius: 1.0,
+    height: 6.0,
+    step_height: 2.5,
+};
+
+
+// A 20 unit square room.
+fn room() -> World {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &rect(0.0, 0.0, 20.0, 20.0));
+    builder.build()
+}
+
+// Two rooms side by side with a portal along x = 10, the right one with the given floor and
+// ceiling.
+fn two_rooms(floor_elev: f32, ceiling_elev: f32) -> World {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &rect(0.0, 0.0, 10.0, 20.0));
+    builder.add_sector(info(floor_elev, ceiling_elev), texture, &rect(10.0, 0.0, 20.0, 20.0));
+    builder.build()
+}
+
+fn move_by(world: &World, sector: usize, from: (f32, f32), to: (f32, f32)) -> (usize, Vec2) {
+    let (SectorIndex(sector), pos) = world.move_object(SectorIndex(sector), Vec2::new(from.0, from.1), Vec2::new(to.0, to.1), 0.0, &COLLIDER);
+    (sector, pos)
+}
+
+
+#[test]
+fn closest_point_is_clamped_to_the_segment() {
+    let seg = LineSeg::new(0.0, 0.0, 10.0, 0.0);
+
+    assert_near(seg.closest_point(Vec2::new( 4.0,  3.0)),  4.0, 0.0);
+    assert_near(seg.closest_point(Vec2::new(-2.0,  3.0)),  0.0, 0.0);
+    assert_near(seg.closest_point(Vec2::new(12.0, -3.0)), 10.0, 0.0);
+    assert_near(LineSeg::new(1.0, 1.0, 1.0, 1.0).closest_point(Vec2::new(5.0, 5.0)), 1.0, 1.0);
+}
+
+#[test]
+fn head_on_move_into_wall_stops() {
+    let world = room();
+
+    // Straight into the north wall, both through it and just short of it.
+    assert_near(move_by(&world, 0, (10.0, 3.0), (10.0, -5.0)).1, 10.0, 3.0);
+    assert_near(move_by(&world, 0, (10.0, 3.0), (10.0, 0.5)).1, 10.0, 3.0);
+}
+
+#[test]
+fn move_up_to_radius_is_allowed() {
+    let world = room();
+    assert_near(move_by(&world, 0, (10.0, 3.0), (10.0, 1.5)).1, 10.0, 1.5);
+}
+
+#[test]
+fn diagonal_move_slides_along_wall() {
+    let world = room();
+    assert_near(move_by(&world, 0, (10.0, 1.5), (12.0, 0.5)).1, 12.0, 1.5);
+}
+
+#[test]
+fn move_parallel_to_touching_wall_is_allowed() {
+    // Already closer than the radius, which can happen after a sector's floor moves.
+    let world = room();
+    assert_near(move_by(&world, 0, (10.0, 0.5), (12.0, 0.5)).1, 12.0, 0.5);
+}
+
+#[test]
+fn move_into_corner_stops() {
+    let world = room();
+    let (_, pos) = move_by(&world, 0, (1.5, 1.5), (0.5, 0.5));
+
+    assert_near(pos, 1.5, 1.5);
+}
+
+#[test]
+fn move_along_wall_into_corner_keeps_radius() {
+    let world = room();
+    let (_, pos) = move_by(&world, 0, (3.0, 1.5), (0.0, 0.5));
+
+    assert!(pos.x >= COLLIDER.radius - EPSILON && pos.y >= COLLIDER.radius - EPSILON, "ended at ({}, {})", pos.x, pos.y);
+}
+
+#[test]
+fn crossing_portal_changes_sector() {
+    let world = two_rooms(0.0, 20.0);
+
+    let (sector, pos) = move_by(&world, 0, (9.5, 5.0), (10.5, 5.0));
+    assert_eq!(sector, 1);
+    assert_near(pos, 10.5, 5.0);
+}
+
+#[test]
+fn portal_within_radius_does_not_block() {
+    let world = two_rooms(0.0, 20.0);
+
+    let (sector, pos) = move_by(&world, 0, (8.0, 5.0), (9.5, 5.0));
+    assert_eq!(sector, 0);
+    assert_near(pos, 9.5, 5.0);
+}
+
+#[test]
+fn step_up_to_step_height_is_allowed() {
+    for &floor_elev in &[1.0, COLLIDER.step_height] {
+        let world = two_rooms(floor_elev, 20.0);
+        assert!(world.can_enter(SectorIndex(0), SectorIndex(1), 0.0, &COLLIDER), "step of {}", floor_elev);
+
+        let (sector, pos) = move_by(&world, 0, (9.5, 5.0), (10.5, 5.0));
+        assert_eq!(sector, 1, "step of {}", floor_elev);
+        assert_near(pos, 10.5, 5.0);
+    }
+}
+
+#[test]
+fn step_down_is_allowed_from_any_height() {
+    let world = two_rooms(-50.0, 20.0);
+    assert!(world.can_enter(SectorIndex(0), SectorIndex(1), 0.0, &COLLIDER));
+}
+
+#[test]
+fn step_above_step_height_blocks() {
+    let world = two_rooms(COLLIDER.step_height + 0.1, 20.0);
+    assert!(!world.can_enter(SectorIndex(0), SectorIndex(1), 0.0, &COLLIDER));
+
+    // The portal acts as a solid wall, radius included.
+    let (sector, pos) = move_by(&world, 0, (9.5, 5.0), (10.5, 5.0));
+    assert_eq!(sector, 0);
+    assert_near(pos, 9.5, 5.0);
+
+    let (sector, pos) = move_by(&world, 0, (8.0, 5.0), (9.5, 5.0));
+    assert_eq!(sector, 0);
+    assert_near(pos, 8.0, 5.0);
+}
+
+#[test]
+fn higher_feet_can_reach_a_taller_step() {
+    // Mid jump, say.
+    let world = two_rooms(5.0, 20.0);
+    assert!(world.can_enter(SectorIndex(0), SectorIndex(1), 3.0, &COLLIDER));
+}
+
+#[test]
+fn low_ceiling_blocks() {
+    let world = two_rooms(0.0, COLLIDER.height - 0.5);
+    assert!(!world.can_enter(SectorIndex(0), SectorIndex(1), 0.0, &COLLIDER));
+    assert_eq!(move_by(&world, 0, (9.5, 5.0), (10.5, 5.0)).0, 0);
+}
+
+#[test]
+fn opening_too_low_for_step_blocks() {
+    // Tall enough inside, but stepping up leaves too little room under this side's ceiling.
+    let world = two_rooms(2.0, 30.0);
+    let tall = Collider { height: 19.0, ..COLLIDER };
+    assert!(!world.can_enter(SectorIndex(0), SectorIndex(1), 0.0, &tall));
+}
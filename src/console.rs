@@ -0,0 +1,304 @@
+use std::fmt;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+
+use automap;
+use game::{Game, MoveMode};
+use math::approach;
+
+
+pub const MAX_LINES: usize = 100;
+const MAX_HISTORY: usize = 50;
+// Fraction of the way open the console slides per second.
+const SLIDE_SPEED: f32 = 4.0;
+
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum CvarValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+}
+
+// A value that can be read and set from the console. `set` is only ever given a value of the
+// same type `get` returns.
+pub struct Cvar {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub get: fn(&Game) -> CvarValue,
+    pub set: fn(&mut Game, CvarValue),
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+}
+
+pub enum Command {
+    Help,
+    MoveMode(MoveMode),
+    Teleport(f32, f32),
+    Map(String),
+    Screenshot(Option<String>),
+    GetCvar(&'static Cvar),
+    SetCvar(&'static Cvar, CvarValue),
+}
+
+pub struct Console {
+    pub open: bool,
+    _slide: f32,
+    _input: String,
+    _lines: Vec<String>,
+    _history: Vec<String>,
+    _history_pos: Option<usize>,
+    _submitted: Vec<String>,
+}
+
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec { name: "help",       usage: "help",              help: "list commands and variables" },
+    CommandSpec { name: "noclip",     usage: "noclip",            help: "toggle walking through walls" },
+    CommandSpec { name: "fly",        usage: "fly",               help: "toggle flying through walls and floors" },
+    CommandSpec { name: "freecam",    usage: "freecam",           help: "toggle moving the view without the player" },
+    CommandSpec { name: "teleport",   usage: "teleport <x> <y>",  help: "move the player to a point" },
+    CommandSpec { name: "map",        usage: "map <name>",        help: "load maps/<name>.map" },
+    CommandSpec { name: "screenshot", usage: "screenshot [file]", help: "save the current frame as a png" },
+];
+
+pub const CVARS: &[Cvar] = &[
+    Cvar {
+        name: "speed", help: "walking speed in units per second",
+        get: |game| CvarValue::Float(game.tuning.speed),
+        set: |game, value| game.tuning.speed = value.float(),
+    },
+    Cvar {
+        name: "turn", help: "keyboard turning speed in radians per second",
+        get: |game| CvarValue::Float(game.tuning.turn),
+        set: |game, value| game.tuning.turn = value.float(),
+    },
+    Cvar {
+        name: "gravity", help: "downward acceleration in units per second squared",
+        get: |game| CvarValue::Float(game.tuning.gravity),
+        set: |game, value| game.tuning.gravity = value.float(),
+    },
+    Cvar {
+        name: "jump_speed", help: "upward speed at the start of a jump",
+        get: |game| CvarValue::Float(game.tuning.jump_speed),
+        set: |game, value| game.tuning.jump_speed = value.float(),
+    },
+    Cvar {
+        name: "eye_height", help: "eye height above the floor when standing",
+        get: |game| CvarValue::Float(game.tuning.eye_height),
+        set: |game, value| game.tuning.eye_height = value.float().max(0.0),
+    },
+    Cvar {
+        name: "fov", help: "horizontal field of view in degrees",
+        get: |game| CvarValue::Float(game.fov),
+        set: |game, value| game.fov = value.float().min(179.0).max(1.0),
+    },
+    Cvar {
+        name: "map_scale", help: "automap zoom in pixels per unit",
+        get: |game| CvarValue::Float(game.automap.scale),
+        set: |game, value| game.automap.scale = value.float().min(automap::MAX_SCALE).max(automap::MIN_SCALE),
+    },
+    Cvar {
+        name: "overlay_opacity", help: "opacity of the map overlay from 0 to 1",
+        get: |game| CvarValue::Float(game.automap.overlay.opacity),
+        set: |game, value| game.automap.overlay.opacity = value.float().min(1.0).max(0.0),
+    },
+    Cvar {
+        name: "sensitivity", help: "mouse sensitivity",
+        get: |game| CvarValue::Float(game.mouse.sensitivity),
+        set: |game, value| game.mouse.sensitivity = value.float(),
+    },
+    Cvar {
+        name: "invert_y", help: "invert vertical mouse look",
+        get: |game| CvarValue::Bool(game.mouse.invert_y),
+        set: |game, value| game.mouse.invert_y = value.bool(),
+    },
+    Cvar {
+        name: "render_threads", help: "number of threads rendering the view",
+        get: |game| CvarValue::Int(game.render_threads as i64),
+        set: |game, value| game.render_threads = value.int().max(1) as usize,
+    },
+];
+
+
+impl CvarValue {
+    // Parses `text` as the same type as `self`.
+    pub fn parse_as(self, text: &str) -> Result<CvarValue, String> {
+        match self {
+            CvarValue::Bool(_) => match text {
+                "1" | "true" | "on"   => Ok(CvarValue::Bool(true)),
+                "0" | "false" | "off" => Ok(CvarValue::Bool(false)),
+                _ => Err(format!("expected true or false, got `{}`", text)),
+            },
+            CvarValue::Int(_) => text.parse().map(CvarValue::Int).map_err(|_| format!("expected an integer, got `{}`", text)),
+            CvarValue::Float(_) => text.parse().map(CvarValue::Float).map_err(|_| format!("expected a number, got `{}`", text)),
+        }
+    }
+
+    pub fn bool(self) -> bool {
+        match self { CvarValue::Bool(b) => b, _ => panic!("cvar is not a bool") }
+    }
+    pub fn int(self) -> i64 {
+        match self { CvarValue::Int(i) => i, _ => panic!("cvar is not an integer") }
+    }
+    pub fn float(self) -> f32 {
+        match self { CvarValue::Float(f) => f, _ => panic!("cvar is not a number") }
+    }
+}
+
+impl fmt::Display for CvarValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CvarValue::Bool(b)  => write!(f, "{}", b),
+            CvarValue::Int(i)   => write!(f, "{}", i),
+            CvarValue::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+// A command is a name followed by its arguments. A variable's name on its own prints its
+// value, followed by a value it sets it.
+pub fn parse_command(game: &Game, line: &str) -> Result<Command, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let num = |s: &str| s.parse::<f32>().map_err(|_| format!("invalid number `{}`", s));
+
+    match tokens.as_slice() {
+        ["help"] => Ok(Command::Help),
+        ["noclip"] => Ok(Command::MoveMode(MoveMode::Noclip)),
+        ["fly"] => Ok(Command::MoveMode(MoveMode::Fly)),
+        ["freecam"] => Ok(Command::MoveMode(MoveMode::Freecam)),
+        ["teleport", x, y] => Ok(Command::Teleport(num(x)?, num(y)?)),
+        ["map", name] => Ok(Command::Map(name.to_string())),
+        ["screenshot"] => Ok(Command::Screenshot(None)),
+        ["screenshot", path] => Ok(Command::Screenshot(Some(path.to_string()))),
+        [name, args @ ..] => {
+            if let Some(spec) = COMMANDS.iter().find(|spec| spec.name == *name) {
+                return Err(format!("usage: {}", spec.usage));
+            }
+            let cvar = CVARS.iter().find(|cvar| cvar.name == *name).ok_or(format!("unknown command `{}`", name))?;
+            match args {
+                [] => Ok(Command::GetCvar(cvar)),
+                [value] => Ok(Command::SetCvar(cvar, (cvar.get)(game).parse_as(value)?)),
+                _ => Err(format!("usage: {} [value]", cvar.name)),
+            }
+        }
+        [] => Err("empty command".to_string()),
+    }
+}
+
+impl Console {
+    pub fn new() -> Console {
+        Console {
+            open: false,
+            _slide: 0.0,
+            _input: String::new(),
+            _lines: vec![],
+            _history: vec![],
+            _history_pos: None,
+            _submitted: vec![],
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    // How far the console has dropped down, from 0 when hidden to 1 when fully open.
+    pub fn get_slide(&self) -> f32 {
+        self._slide
+    }
+
+    pub fn get_input(&self) -> &str {
+        &self._input
+    }
+
+    pub fn get_lines(&self) -> &[String] {
+        &self._lines
+    }
+
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self._lines.push(line.to_string());
+        }
+        if self._lines.len() > MAX_LINES {
+            let excess = self._lines.len() - MAX_LINES;
+            self._lines.drain(..excess);
+        }
+    }
+
+    // Lines entered since the last call, oldest first.
+    pub fn take_submitted(&mut self) -> Vec<String> {
+        self._submitted.drain(..).collect()
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        let target = if self.open { 1.0 } else { 0.0 };
+        self._slide = approach(self._slide, target, SLIDE_SPEED * dt);
+    }
+
+    // Takes keyboard events while the console is open.
+    pub fn check_event(&mut self, event: &Event) {
+        match *event {
+            Event::TextInput { ref text, .. } => {
+                self._input.push_str(text);
+            }
+            Event::KeyDown { keycode: Some(keycode), .. } => match keycode {
+                Keycode::Return    => self.submit(),
+                Keycode::Backspace => { self._input.pop(); }
+                Keycode::Escape    => self.open = false,
+                Keycode::Up        => self.recall(-1),
+                Keycode::Down      => self.recall(1),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn submit(&mut self) {
+        let line = self._input.trim().to_string();
+        self._input.clear();
+        self._history_pos = None;
+
+        if line.is_empty() {
+            return;
+        }
+
+        self.print(&format!("> {}", line));
+        if self._history.last() != Some(&line) {
+            self._history.push(line.clone());
+            if self._history.len() > MAX_HISTORY {
+                self._history.remove(0);
+            }
+        }
+        self._submitted.push(line);
+    }
+
+    // Steps through earlier input, `direction` -1 going back in time. Stepping forward past the
+    // newest entry leaves an empty line.
+    fn recall(&mut self, direction: isize) {
+        if self._history.is_empty() {
+            return;
+        }
+
+        let last = self._history.len() as isize - 1;
+        let pos = match self._history_pos {
+            Some(pos) => pos as isize + direction,
+            None if direction < 0 => last,
+            None => return,
+        };
+
+        if pos > last {
+            self._history_pos = None;
+            self._input.clear();
+        } else {
+            let pos = pos.max(0) as usize;
+            self._history_pos = Some(pos);
+            self._input = self._history[pos].clone();
+        }
+    }
+}
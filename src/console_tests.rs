@@ -0,0 +1,159 @@
+// Tests for parsing console commands and variables, and for the console's input line, history
+// and scrollback, driven by the same events the game passes it.
+
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, NOMOD};
+
+use automap;
+use console::{self, Console, Command, CvarValue};
+use game::{Game, MoveMode};
+use math::V2_ORIGIN;
+use world::{self, SectorIndex};
+
+
+fn game() -> Game {
+    Game::new(world::temp(), SectorIndex(0), V2_ORIGIN, 0.0)
+}
+
+fn parse_error(line: &str) -> String {
+    match console::parse_command(&game(), line) {
+        Ok(_) => panic!("expected `{}` to be rejected", line),
+        Err(err) => err,
+    }
+}
+
+fn press(console: &mut Console, keycode: Keycode) {
+    console.check_event(&Event::KeyDown { timestamp: 0, window_id: 0, keycode: Some(keycode), scancode: None, keymod: NOMOD, repeat: false });
+}
+
+fn enter(console: &mut Console, line: &str) {
+    console.check_event(&Event::TextInput { timestamp: 0, window_id: 0, text: line.to_string() });
+    press(console, Keycode::Return);
+}
+
+
+#[test]
+fn commands_parse_their_arguments() {
+    let game = game();
+
+    match console::parse_command(&game, "  teleport 1.5 -2 ") {
+        Ok(Command::Teleport(x, y)) => assert_eq!((x, y), (1.5, -2.0)),
+        _ => panic!("expected a teleport"),
+    }
+    match console::parse_command(&game, "fly") {
+        Ok(Command::MoveMode(mode)) => assert_eq!(mode, MoveMode::Fly),
+        _ => panic!("expected a move mode"),
+    }
+    match console::parse_command(&game, "map e1m1") {
+        Ok(Command::Map(name)) => assert_eq!(name, "e1m1"),
+        _ => panic!("expected a map"),
+    }
+    match console::parse_command(&game, "screenshot") {
+        Ok(Command::Screenshot(path)) => assert_eq!(path, None),
+        _ => panic!("expected a screenshot"),
+    }
+}
+
+#[test]
+fn wrong_arguments_print_usage() {
+    assert_eq!(parse_error("teleport 1"), "usage: teleport <x> <y>");
+    assert_eq!(parse_error("map"), "usage: map <name>");
+    assert_eq!(parse_error("help me"), "usage: help");
+    assert_eq!(parse_error("screenshot a.png b.png"), "usage: screenshot [file]");
+    assert_eq!(parse_error("speed 1 2"), "usage: speed [value]");
+    assert_eq!(parse_error("teleport north 2"), "invalid number `north`");
+    assert_eq!(parse_error("jump"), "unknown command `jump`");
+    assert_eq!(parse_error("   "), "empty command");
+}
+
+#[test]
+fn cvars_get_and_set() {
+    let game = game();
+
+    match console::parse_command(&game, "speed") {
+        Ok(Command::GetCvar(cvar)) => assert_eq!(cvar.name, "speed"),
+        _ => panic!("expected a get"),
+    }
+    match console::parse_command(&game, "invert_y on") {
+        Ok(Command::SetCvar(cvar, value)) => assert_eq!((cvar.name, value), ("invert_y", CvarValue::Bool(true))),
+        _ => panic!("expected a set"),
+    }
+}
+
+#[test]
+fn cvar_values_must_match_the_type() {
+    assert_eq!(parse_error("render_threads 1.5"), "expected an integer, got `1.5`");
+    assert_eq!(parse_error("invert_y maybe"), "expected true or false, got `maybe`");
+    assert_eq!(parse_error("speed fast"), "expected a number, got `fast`");
+}
+
+#[test]
+fn parse_as_keeps_the_type() {
+    assert_eq!(CvarValue::Bool(false).parse_as("1"), Ok(CvarValue::Bool(true)));
+    assert_eq!(CvarValue::Bool(true).parse_as("off"), Ok(CvarValue::Bool(false)));
+    assert_eq!(CvarValue::Int(0).parse_as("-3"), Ok(CvarValue::Int(-3)));
+    assert_eq!(CvarValue::Float(0.0).parse_as("2"), Ok(CvarValue::Float(2.0)));
+    assert!(CvarValue::Bool(false).parse_as("yes").is_err());
+    assert!(CvarValue::Int(0).parse_as("").is_err());
+}
+
+#[test]
+fn map_scale_is_clamped_like_zooming() {
+    let mut game = game();
+
+    for &(value, expected) in &[("100", automap::MAX_SCALE), ("0", automap::MIN_SCALE), ("4", 4.0)] {
+        let command = console::parse_command(&game, &format!("map_scale {}", value)).unwrap();
+        game.run_command(command).unwrap();
+        assert_eq!(game.automap.scale, expected);
+    }
+}
+
+#[test]
+fn submit_echoes_and_queues_the_line() {
+    let mut console = Console::new();
+    enter(&mut console, "  speed 20 ");
+    enter(&mut console, "   ");
+
+    assert_eq!(console.take_submitted(), vec!["speed 20".to_string()]);
+    assert!(console.take_submitted().is_empty());
+    assert_eq!(console.get_lines(), &["> speed 20".to_string()]);
+    assert_eq!(console.get_input(), "");
+}
+
+#[test]
+fn history_skips_repeats_and_stops_at_either_end() {
+    let mut console = Console::new();
+    for line in &["help", "fly", "fly", "noclip"] {
+        enter(&mut console, line);
+    }
+
+    let mut recalled = vec![];
+    for &keycode in &[Keycode::Up, Keycode::Up, Keycode::Up, Keycode::Up, Keycode::Down, Keycode::Down, Keycode::Down, Keycode::Down] {
+        press(&mut console, keycode);
+        recalled.push(console.get_input().to_string());
+    }
+
+    assert_eq!(recalled, vec!["noclip", "fly", "help", "help", "fly", "noclip", "", ""]);
+}
+
+#[test]
+fn history_is_left_alone_when_empty() {
+    let mut console = Console::new();
+    console.check_event(&Event::TextInput { timestamp: 0, window_id: 0, text: "hel".to_string() });
+    press(&mut console, Keycode::Up);
+    press(&mut console, Keycode::Down);
+
+    assert_eq!(console.get_input(), "hel");
+}
+
+#[test]
+fn scrollback_keeps_the_newest_lines() {
+    let mut console = Console::new();
+    let text: Vec<String> = (0..console::MAX_LINES + 20).map(|i| format!("line {}", i)).collect();
+    console.print(&text.join("\n"));
+
+    let lines = console.get_lines();
+    assert_eq!(lines.len(), console::MAX_LINES);
+    assert_eq!(lines[0], "line 20");
+    assert_eq!(lines[console::MAX_LINES - 1], format!("line {}", console::MAX_LINES + 19));
+}
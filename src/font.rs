@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use texture::Texture;
+
+
+// The atlas is a grid of ATLAS_COLUMNS by ATLAS_ROWS equally sized cells holding the
+// characters from FIRST_CHAR onwards in ASCII order, drawn in white on transparent.
+const ATLAS_COLUMNS: usize = 16;
+const ATLAS_ROWS: usize = 6;
+const FIRST_CHAR: u32 = 32;
+// Characters missing from the atlas are drawn as this instead.
+const FALLBACK_CHAR: char = '?';
+
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// A monospaced bitmap font, every character advances by one cell of the atlas and every line
+// by one cell height.
+pub struct Font {
+    pub glyph_width: usize,
+    pub glyph_height: usize,
+    _atlas: Texture,
+}
+
+
+impl Font {
+    pub fn load(path: &Path) -> Result<Font, String> {
+        let atlas = Texture::load(path)?;
+
+        if atlas.width % ATLAS_COLUMNS != 0 || atlas.height % ATLAS_ROWS != 0 {
+            return Err(format!("{}: font atlas must be a {}x{} grid of cells", path.display(), ATLAS_COLUMNS, ATLAS_ROWS));
+        }
+
+        Ok(Font {
+            glyph_width: atlas.width / ATLAS_COLUMNS,
+            glyph_height: atlas.height / ATLAS_ROWS,
+            _atlas: atlas,
+        })
+    }
+
+    // Width in pixels of the longest line.
+    pub fn text_width(&self, text: &str) -> usize {
+        text.lines().map(|line| line.chars().count()).max().unwrap_or(0) * self.glyph_width
+    }
+
+    pub fn text_height(&self, text: &str) -> usize {
+        text.lines().count() * self.glyph_height
+    }
+
+    // Whether pixel (`x`, `y`) of the character's cell is part of the glyph.
+    pub fn is_set(&self, c: char, x: usize, y: usize) -> bool {
+        let index = self.glyph_index(c);
+        let cell_x = (index % ATLAS_COLUMNS) * self.glyph_width;
+        let cell_y = (index / ATLAS_COLUMNS) * self.glyph_height;
+        let (_, _, _, a) = self._atlas.sample_rgba((cell_x + x) as f32, (cell_y + y) as f32);
+        a >= 0x80
+    }
+
+    fn glyph_index(&self, c: char) -> usize {
+        let code = c as u32;
+        if code >= FIRST_CHAR && code < FIRST_CHAR + (ATLAS_COLUMNS * ATLAS_ROWS) as u32 {
+            (code - FIRST_CHAR) as usize
+        } else {
+            (FALLBACK_CHAR as u32 - FIRST_CHAR) as usize
+        }
+    }
+}
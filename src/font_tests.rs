@@ -0,0 +1,138 @@
+// Tests for laying out text with `res/font.png`, and for drawing it with
+// `RenderContext::draw_text`.
+
+use std::ops::Range;
+use std::path::Path;
+
+use font::{Font, Align};
+use render::RenderContext;
+
+
+const W: usize = 64;
+const H: usize = 32;
+const WHITE: (u8,u8,u8) = (0xff, 0xff, 0xff);
+
+
+fn font() -> Font {
+    Font::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res/font.png")).unwrap()
+}
+
+// Which pixels of the character's cell are set.
+fn glyph(font: &Font, c: char) -> Vec<bool> {
+    (0..font.glyph_height)
+        .flat_map(|y| (0..font.glyph_width).map(move |x| (x, y)))
+        .map(|(x, y)| font.is_set(c, x, y))
+        .collect()
+}
+
+// Draws each of `texts` onto one blank W by H frame.
+fn draw(font: &Font, texts: &[(&str, isize, isize, Align)]) -> Vec<u8> {
+    draw_band(font, texts, W, H, 0..W)
+}
+
+// Draws each of `texts` onto a blank band of `columns` out of a `width` by `height` frame.
+fn draw_band(font: &Font, texts: &[(&str, isize, isize, Align)], width: usize, height: usize, columns: Range<usize>) -> Vec<u8> {
+    let mut pixels = vec![0u8; 4 * columns.len() * height];
+    {
+        let mut ctx = RenderContext {
+            pixels: &mut pixels,
+            width: width as isize,
+            height: height as isize,
+            columns: columns,
+            visplane_dist: 0.0,
+        };
+        for &(text, x, y, align) in texts.iter() {
+            ctx.draw_text(font, text, x, y, align, WHITE);
+        }
+    }
+    pixels
+}
+
+
+#[test]
+fn glyphs_are_cells_of_the_atlas() {
+    let font = font();
+    assert_eq!((font.glyph_width, font.glyph_height), (6, 8));
+    assert!(glyph(&font, 'A').iter().any(|&set| set));
+    assert!(glyph(&font, ' ').iter().all(|&set| !set));
+    assert!(glyph(&font, 'A') != glyph(&font, 'B'));
+}
+
+#[test]
+fn characters_outside_the_atlas_fall_back_to_question_mark() {
+    let font = font();
+    let question_mark = glyph(&font, '?');
+
+    for &c in &['\u{1f}', '\u{80}', 'é', '→'] {
+        assert!(glyph(&font, c) == question_mark, "{:?}", c);
+    }
+    assert!(glyph(&font, '~') != question_mark);
+}
+
+#[test]
+fn text_width_is_the_longest_line() {
+    let font = font();
+    assert_eq!(font.text_width(""), 0);
+    assert_eq!(font.text_width("FPS"), 3 * 6);
+    assert_eq!(font.text_width("X 1.0\nSECTOR 12"), 9 * 6);
+    // Characters count once each, whatever their encoded length.
+    assert_eq!(font.text_width("é?"), 2 * 6);
+}
+
+#[test]
+fn text_height_counts_lines() {
+    let font = font();
+    assert_eq!(font.text_height(""), 0);
+    assert_eq!(font.text_height("one"), 8);
+    assert_eq!(font.text_height("one\ntwo\nthree"), 3 * 8);
+    assert_eq!(font.text_height("trailing\n"), 8);
+}
+
+#[test]
+fn alignment_moves_each_line_by_its_width() {
+    let font = font();
+    let left = draw(&font, &[("ABC", 10, 4, Align::Left)]);
+
+    assert!(left.iter().any(|&c| c != 0));
+    assert!(draw(&font, &[("ABC", 10 + 9, 4, Align::Center)]) == left);
+    assert!(draw(&font, &[("ABC", 10 + 18, 4, Align::Right)]) == left);
+}
+
+#[test]
+fn lines_are_stacked_and_aligned_separately() {
+    let font = font();
+    let lines = draw(&font, &[("AB", 40 - 12, 4, Align::Left), ("C", 40 - 6, 12, Align::Left)]);
+
+    assert!(draw(&font, &[("AB\nC", 40, 4, Align::Right)]) == lines);
+}
+
+// The rows of a frame `width` pixels wide, cut down to `columns`.
+fn rows(pixels: &[u8], width: usize, columns: Range<usize>) -> Vec<&[u8]> {
+    pixels.chunks(4 * width).map(|row| &row[4 * columns.start .. 4 * columns.end]).collect()
+}
+
+fn lit(pixels: &[u8]) -> usize {
+    pixels.chunks(4).filter(|px| px.iter().any(|&c| c != 0)).count()
+}
+
+// Text running off any edge is cut off rather than written outside the frame or wrapped onto
+// other rows. What is left is the same as the middle of a bigger frame the text fits in whole.
+#[test]
+fn text_is_clipped_to_the_frame() {
+    const MARGIN: usize = 32;
+    let font = font();
+    let texts = [("ABCDEFGHIJKL", -20, -4, Align::Left), ("WXYZ", 60, 28, Align::Left)];
+    let clipped = draw(&font, &texts);
+
+    let shifted: Vec<_> = texts.iter().map(|&(text, x, y, align)| (text, x + MARGIN as isize, y + MARGIN as isize, align)).collect();
+    let whole = draw_band(&font, &shifted, W + 2 * MARGIN, H + 2 * MARGIN, 0..W + 2 * MARGIN);
+    let middle = rows(&whole, W + 2 * MARGIN, MARGIN..MARGIN + W)[MARGIN..MARGIN + H].to_vec();
+
+    assert!(rows(&clipped, W, 0..W) == middle);
+    assert!(lit(&clipped) > 0);
+    assert!(lit(&whole) > lit(&clipped));
+
+    // A band only takes the columns it covers.
+    let band = draw_band(&font, &texts, W, H, 16..40);
+    assert!(rows(&band, 24, 0..24) == rows(&clipped, W, 16..40));
+}
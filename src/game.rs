@@ -1,49 +1,481 @@
-use world::{World, SectorIndex};
-use math::{Vec2};
+use std::mem;
+use std::thread;
+
+use world::{World, SectorIndex, WallIndex, Collider};
+use math::{Vec2, LineSeg, approach};
 use input::{InputState, Key};
+use sprite::Sprite;
+use mover::{Mover, Trigger};
+use settings::{DEFAULT_FOV, MouseSettings};
+use automap::Automap;
+use hud::Hud;
+use console::{self, Console, Command};
+use render::RenderPool;
 
 
-const SPEED: f32 = 0.3;
-const TURN: f32 = 0.03;
+const USE_RANGE: f32 = 6.0;
+const PLAYER_RADIUS: f32 = 1.5;
+const PLAYER_HEIGHT: f32 = 6.0;
+const STEP_HEIGHT: f32 = 2.5;
+const CROUCH_HEIGHT: f32 = 3.5;
+const CROUCH_EYE_HEIGHT: f32 = 2.5;
+const CROUCH_SPEED: f32 = 15.0;
+const CROUCH_MOVE_SCALE: f32 = 0.5;
+const STEP_SMOOTH_SPEED: f32 = 21.0;
+// Radians of turn, and pixels of look, per unit of mouse motion at a sensitivity of 1.
+const MOUSE_TURN: f32 = 1.0 / 500.0;
+const MOUSE_LOOK: f32 = 0.5;
 
 
-pub struct Game<'a> {
+pub struct Game {
     pub sector: SectorIndex,
     pub pos: Vec2,
     pub face_angle: f32,
     pub look_angle: f32,
-    pub world: &'a World,
+    pub z: f32,
+    pub z_vel: f32,
+    pub eye_height: f32,
+    pub on_ground: bool,
+    pub world: World,
+    pub sprites: Vec<Sprite>,
+    pub movers: Vec<Mover>,
+    pub collider: Collider,
     pub show_map: bool,
-    pub t: f32
+    pub automap: Automap,
+    pub hud: Hud,
+    pub render_threads: usize,
+    pub render_pool: RenderPool,
+    pub fov: f32,
+    pub mouse: MouseSettings,
+    pub tuning: Tuning,
+    pub console: Console,
+    pub move_mode: MoveMode,
+    pub freecam: Camera,
+    pub t: f32,
+    _use_held: bool,
+    _seen_only_held: bool,
+    _overlay_held: bool,
+    _move_mode_held: bool,
+    _step_offset: f32,
+    _prev: Camera,
 }
 
+// Debug movement. Noclip passes through walls, fly also leaves the floor behind and moves
+// straight up and down with jump and crouch. Freecam flies the view around on its own while
+// the player stays where they are.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum MoveMode {
+    Walk,
+    Noclip,
+    Fly,
+    Freecam,
+}
 
-impl<'a> Game<'a> {
-    pub fn step(&mut self, input: &InputState) {
-        self.face_angle += input.mouse_dx() / 500.0;
-        self.look_angle += input.mouse_dy() / 2.0;
+// Movement values which can be changed from the console, speeds are per second.
+#[derive(Clone,Copy)]
+pub struct Tuning {
+    pub speed: f32,
+    pub turn: f32,
+    pub gravity: f32,
+    pub jump_speed: f32,
+    pub eye_height: f32,
+}
+
+// Where the view is rendered from, see `Game::camera`.
+#[derive(Clone,Copy)]
+pub struct Camera {
+    pub sector: SectorIndex,
+    pub pos: Vec2,
+    pub eye_elev: f32,
+    pub face_angle: f32,
+    pub look_angle: f32,
+}
 
-        self.look_angle = self.look_angle.min(120.0).max(-120.0);
 
-        if input.has_key(Key::Forward) { self.do_move( 1.0,  0.0); }
-        if input.has_key(Key::Back)    { self.do_move(-1.0,  0.0); }
-        if input.has_key(Key::Left)    { self.do_move( 0.0, -1.0); }
-        if input.has_key(Key::Right)   { self.do_move( 0.0,  1.0); }
+impl MoveMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            MoveMode::Walk    => "walk",
+            MoveMode::Noclip  => "noclip",
+            MoveMode::Fly     => "fly",
+            MoveMode::Freecam => "freecam",
+        }
+    }
 
-        self.show_map = input.has_key(Key::ShowMap);
-        self.t += 0.02;
+    pub fn next(self) -> MoveMode {
+        match self {
+            MoveMode::Walk    => MoveMode::Noclip,
+            MoveMode::Noclip  => MoveMode::Fly,
+            MoveMode::Fly     => MoveMode::Freecam,
+            MoveMode::Freecam => MoveMode::Walk,
+        }
     }
+}
+
+impl Tuning {
+    pub fn default() -> Tuning {
+        Tuning {
+            speed: 18.0,
+            turn: 1.8,
+            gravity: 144.0,
+            jump_speed: 33.0,
+            eye_height: 5.0,
+        }
+    }
+}
+
+// `para` moves along `face_angle` and `perp` to the right of it.
+fn move_delta(face_angle: f32, speed: f32, para: f32, perp: f32) -> Vec2 {
+    let sin = speed*face_angle.sin();
+    let cos = speed*face_angle.cos();
+
+    Vec2::new(
+         sin*para + cos*perp,
+        -cos*para + sin*perp
+    )
+}
+
+// One render thread per core unless told otherwise.
+pub fn default_render_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+impl Game {
+    pub fn new(world: World, sector: SectorIndex, pos: Vec2, face_angle: f32) -> Game {
+        let z = world.get_elevation(sector);
+        let tuning = Tuning::default();
+        let camera = Camera {
+            sector: sector,
+            pos: pos,
+            eye_elev: z + tuning.eye_height,
+            face_angle: face_angle,
+            look_angle: 0.0,
+        };
+
+        Game {
+            sector: sector,
+            pos: pos,
+            face_angle: face_angle,
+            look_angle: 0.0,
+            z: z,
+            z_vel: 0.0,
+            eye_height: tuning.eye_height,
+            on_ground: true,
+            sprites: world.get_sprites().to_vec(),
+            movers: world.get_movers().to_vec(),
+            collider: Collider {
+                radius: PLAYER_RADIUS,
+                height: PLAYER_HEIGHT,
+                step_height: STEP_HEIGHT,
+            },
+            automap: Automap::new(&world),
+            hud: Hud::new(),
+            world: world,
+            show_map: false,
+            render_threads: default_render_threads(),
+            render_pool: RenderPool::new(),
+            fov: DEFAULT_FOV,
+            mouse: MouseSettings::default(),
+            tuning: tuning,
+            console: Console::new(),
+            move_mode: MoveMode::Walk,
+            freecam: camera,
+            t: 0.0,
+            _use_held: false,
+            _seen_only_held: false,
+            _overlay_held: false,
+            _move_mode_held: false,
+            _step_offset: 0.0,
+            _prev: camera,
+        }
+    }
+
+    // The camera lags behind the feet when stepping up so that stairs don't jolt the view.
+    pub fn eye_elev(&self) -> f32 {
+        self.z + self.eye_height + self._step_offset
+    }
+
+    // Interpolates between the state before and after the last step. The view stays inside
+    // whichever of the two sectors contains the interpolated position.
+    pub fn camera(&self, alpha: f32) -> Camera {
+        let prev = &self._prev;
+        let next = self.view();
+        let pos = prev.pos + (next.pos - prev.pos) * alpha;
+        let SectorIndex(index) = next.sector;
+
+        Camera {
+            sector: if self.world.get_sectors()[index].contains(pos) { next.sector } else { prev.sector },
+            pos: pos,
+            eye_elev: prev.eye_elev + (next.eye_elev - prev.eye_elev) * alpha,
+            face_angle: prev.face_angle + (next.face_angle - prev.face_angle) * alpha,
+            look_angle: next.look_angle,
+        }
+    }
+
+    // The player's eyes, or the free camera while it is detached.
+    fn view(&self) -> Camera {
+        if self.move_mode == MoveMode::Freecam {
+            return self.freecam;
+        }
+        Camera {
+            sector: self.sector,
+            pos: self.pos,
+            eye_elev: self.eye_elev(),
+            face_angle: self.face_angle,
+            look_angle: self.look_angle,
+        }
+    }
+
+    // The free camera starts out at the player's eyes each time it is detached.
+    pub fn set_move_mode(&mut self, mode: MoveMode) {
+        if mode == MoveMode::Freecam && self.move_mode != MoveMode::Freecam {
+            self.freecam = self.view();
+        }
+        self.move_mode = mode;
+        self._prev = self.camera(1.0);
+    }
+
+    // Mouse look is applied once per rendered frame rather than per step, and is not
+    // interpolated so that it stays responsive.
+    pub fn look(&mut self, input: &InputState) {
+        let turn = input.mouse_dx() * MOUSE_TURN * self.mouse.sensitivity;
+        let look = input.mouse_dy() * MOUSE_LOOK * self.mouse.sensitivity;
+        let look_limit = self.mouse.look_limit;
+
+        let (face_angle, look_angle) = if self.move_mode == MoveMode::Freecam {
+            (&mut self.freecam.face_angle, &mut self.freecam.look_angle)
+        } else {
+            (&mut self.face_angle, &mut self.look_angle)
+        };
+        *face_angle += turn;
+        self._prev.face_angle += turn;
+
+        *look_angle += if self.mouse.invert_y { -look } else { look };
+        *look_angle = look_angle.min(look_limit).max(-look_limit);
+    }
+
+    // Advances the simulation by `dt` seconds.
+    pub fn step(&mut self, dt: f32, input: &InputState) {
+        self._prev = self.camera(1.0);
+
+        let move_mode_down = input.has_key(Key::MoveMode);
+        if move_mode_down && !self._move_mode_held {
+            let mode = self.move_mode.next();
+            self.set_move_mode(mode);
+            self.hud.show_message(mode.name());
+        }
+        self._move_mode_held = move_mode_down;
 
-    fn do_move(&mut self, para: f32, perp: f32) {
-        let sin = SPEED*self.face_angle.sin();
-        let cos = SPEED*self.face_angle.cos();
+        // The player stands still while the free camera has the controls.
+        if self.move_mode == MoveMode::Freecam {
+            self.step_freecam(dt, input);
+            self.step_vertical(dt, false, false);
+        } else {
+            if input.has_key(Key::TurnLeft)  { self.face_angle -= self.tuning.turn * dt; }
+            if input.has_key(Key::TurnRight) { self.face_angle += self.tuning.turn * dt; }
 
-        let new_pos = self.pos + Vec2::new(
-             sin*para + cos*perp,
-            -cos*para + sin*perp
-        );
+            if input.has_key(Key::Forward) { self.do_move(dt,  1.0,  0.0); }
+            if input.has_key(Key::Back)    { self.do_move(dt, -1.0,  0.0); }
+            if input.has_key(Key::Left)    { self.do_move(dt,  0.0, -1.0); }
+            if input.has_key(Key::Right)   { self.do_move(dt,  0.0,  1.0); }
 
-        self.sector = self.world.move_object(self.sector, self.pos, new_pos);
+            self.step_vertical(dt, input.has_key(Key::Crouch), input.has_key(Key::Jump));
+
+            let use_down = input.has_key(Key::Use);
+            if use_down && !self._use_held { self.do_use(); }
+            self._use_held = use_down;
+        }
+
+        // The player keeps movers from closing on them, except when passing through walls.
+        let blocks_movers = self.move_mode != MoveMode::Noclip && self.move_mode != MoveMode::Fly;
+        for mover in self.movers.iter_mut() {
+            let headroom = if blocks_movers && mover.sector == self.sector { Some(self.collider.height) } else { None };
+            mover.step(dt, &mut self.world, headroom);
+        }
+
+        // The map view is reset every time the map is opened.
+        let show_map = input.has_key(Key::ShowMap);
+        if show_map && !self.show_map { self.automap.reset_view(); }
+        self.show_map = show_map;
+        if self.show_map { self.automap.step(dt, input); }
+
+        let seen_only_down = input.has_key(Key::MapSeenOnly);
+        if seen_only_down && !self._seen_only_held { self.automap.seen_only = !self.automap.seen_only; }
+        self._seen_only_held = seen_only_down;
+
+        let overlay_down = input.has_key(Key::MapOverlay);
+        if overlay_down && !self._overlay_held { self.automap.overlay.mode = self.automap.overlay.mode.next(); }
+        self._overlay_held = overlay_down;
+
+        self.hud.step(dt);
+        self.console.step(dt);
+        self.t += dt;
+    }
+
+    // Moves the player onto the floor at `pos` without interpolating from where they were.
+    pub fn teleport(&mut self, pos: Vec2) -> Result<(), String> {
+        let sector = self.world.find_sector(pos).ok_or(format!("{} {} is outside the map", pos.x, pos.y))?;
+        self.sector = sector;
+        self.pos = pos;
+        self.z = self.world.get_elevation(sector);
+        self.z_vel = 0.0;
+        self._step_offset = 0.0;
+        self._prev = self.camera(1.0);
+        Ok(())
+    }
+
+    // Starts over in another world, keeping the settings and anything changed from the console.
+    pub fn change_world(&mut self, world: World, sector: SectorIndex, pos: Vec2, face_angle: f32) {
+        let move_mode = self.move_mode;
+        let mut game = Game::new(world, sector, pos, face_angle);
+        game.hud = mem::replace(&mut self.hud, Hud::new());
+        game.console = mem::replace(&mut self.console, Console::new());
+        game.automap.scale = self.automap.scale;
+        game.automap.overlay = self.automap.overlay;
+        game.render_threads = self.render_threads;
+        game.render_pool = mem::replace(&mut self.render_pool, RenderPool::new());
+        game.fov = self.fov;
+        game.mouse = self.mouse;
+        game.tuning = self.tuning;
+        *self = game;
+        self.set_move_mode(move_mode);
+    }
+
+    // Runs the commands which only affect the game, returning what to print.
+    pub fn run_command(&mut self, command: Command) -> Result<String, String> {
+        match command {
+            Command::Help => {
+                let commands = console::COMMANDS.iter().map(|spec| format!("{:<18} {}", spec.usage, spec.help));
+                let cvars = console::CVARS.iter().map(|cvar| format!("{:<18} {}", cvar.name, cvar.help));
+                Ok(commands.chain(cvars).collect::<Vec<_>>().join("\n"))
+            }
+            Command::MoveMode(mode) => {
+                let mode = if self.move_mode == mode { MoveMode::Walk } else { mode };
+                self.set_move_mode(mode);
+                Ok(format!("{} mode", mode.name()))
+            }
+            Command::Teleport(x, y) => {
+                self.teleport(Vec2::new(x, y))?;
+                Ok(format!("teleported to {} {}", x, y))
+            }
+            Command::GetCvar(cvar) => Ok(format!("{} is {}", cvar.name, (cvar.get)(self))),
+            Command::SetCvar(cvar, value) => {
+                (cvar.set)(self, value);
+                Ok(format!("{} set to {}", cvar.name, (cvar.get)(self)))
+            }
+            Command::Map(_) | Command::Screenshot(_) => Err("command needs the window".to_string()),
+        }
+    }
+
+    fn step_vertical(&mut self, dt: f32, crouch: bool, jump: bool) {
+        self._step_offset = approach(self._step_offset, 0.0, STEP_SMOOTH_SPEED * dt);
+
+        if self.move_mode == MoveMode::Fly {
+            self.collider.height = PLAYER_HEIGHT;
+            self.eye_height = approach(self.eye_height, self.tuning.eye_height, CROUCH_SPEED * dt);
+            self.z_vel = 0.0;
+            self.on_ground = false;
+            if jump   { self.z += self.tuning.speed * dt; }
+            if crouch { self.z -= self.tuning.speed * dt; }
+            return;
+        }
+
+        let floor = self.world.get_elevation(self.sector);
+        let ceiling = self.world.get_ceiling_elevation(self.sector);
+
+        // Stay crouched while there is no room to stand up.
+        let crouching = crouch || ceiling - self.z < PLAYER_HEIGHT;
+        let (height, eye_height) = if crouching {
+            (CROUCH_HEIGHT, CROUCH_EYE_HEIGHT)
+        } else {
+            (PLAYER_HEIGHT, self.tuning.eye_height)
+        };
+        self.collider.height = height;
+        self.eye_height = approach(self.eye_height, eye_height, CROUCH_SPEED * dt);
+
+        if jump && self.on_ground {
+            self.z_vel = self.tuning.jump_speed;
+        }
+
+        self.z_vel -= self.tuning.gravity * dt;
+        self.z += self.z_vel * dt;
+        self.on_ground = self.z <= floor;
+
+        if self.on_ground {
+            self.z = floor;
+            self.z_vel = 0.0;
+        }
+
+        if self.z + height > ceiling {
+            self.z = (ceiling - height).max(floor);
+            self.z_vel = self.z_vel.min(0.0);
+        }
+    }
+
+    // Moves the free camera in the direction it faces, and straight up and down with jump and
+    // crouch. It keeps track of its sector the same way noclip does.
+    fn step_freecam(&mut self, dt: f32, input: &InputState) {
+        let speed = dt * self.tuning.speed;
+        let cam = &mut self.freecam;
+
+        if input.has_key(Key::TurnLeft)  { cam.face_angle -= self.tuning.turn * dt; }
+        if input.has_key(Key::TurnRight) { cam.face_angle += self.tuning.turn * dt; }
+
+        if input.has_key(Key::Forward) { cam.pos = cam.pos + move_delta(cam.face_angle, speed,  1.0,  0.0); }
+        if input.has_key(Key::Back)    { cam.pos = cam.pos + move_delta(cam.face_angle, speed, -1.0,  0.0); }
+        if input.has_key(Key::Left)    { cam.pos = cam.pos + move_delta(cam.face_angle, speed,  0.0, -1.0); }
+        if input.has_key(Key::Right)   { cam.pos = cam.pos + move_delta(cam.face_angle, speed,  0.0,  1.0); }
+
+        if input.has_key(Key::Jump)   { cam.eye_elev += speed; }
+        if input.has_key(Key::Crouch) { cam.eye_elev -= speed; }
+
+        cam.sector = self.world.find_sector(cam.pos).unwrap_or(cam.sector);
+    }
+
+    fn do_move(&mut self, dt: f32, para: f32, perp: f32) {
+        let speed = dt * self.tuning.speed * if self.collider.height < PLAYER_HEIGHT { CROUCH_MOVE_SCALE } else { 1.0 };
+        let new_pos = self.pos + move_delta(self.face_angle, speed, para, perp);
+
+        // Without clipping the player goes anywhere, staying in the last sector when outside
+        // of them all.
+        let (new_sector, new_pos) = if self.move_mode != MoveMode::Walk {
+            (self.world.find_sector(new_pos).unwrap_or(self.sector), new_pos)
+        } else {
+            self.world.move_object(self.sector, self.pos, new_pos, self.z, &self.collider)
+        };
+
+        let move_seg = LineSeg { a: self.pos, b: new_pos };
+        for mover in self.movers.iter_mut() {
+            if let Trigger::Walk(SectorIndex(sector), WallIndex(wall)) = mover.trigger {
+                if move_seg.intersects(self.world.get_sectors()[sector].walls[wall].seg).is_some() {
+                    mover.activate();
+                }
+            }
+        }
+
+        self.sector = new_sector;
         self.pos = new_pos;
+
+        let floor = self.world.get_elevation(self.sector);
+        if self.z < floor && self.move_mode != MoveMode::Fly {
+            self._step_offset -= floor - self.z;
+            self.z = floor;
+        }
+    }
+
+    // Activates use-triggered movers in the sector being stood in, or behind the portal
+    // directly ahead if it is within reach.
+    fn do_use(&mut self) {
+        let facing = self.world.cast_ray(self.sector, self.pos, self.face_angle)
+            .next()
+            .filter(|hit| (hit.hit_pos - self.pos).get_length() < USE_RANGE)
+            .and_then(|hit| hit.wall.portal)
+            .map(|(sector, _)| sector);
+
+        for mover in self.movers.iter_mut().filter(|m| m.trigger == Trigger::Use) {
+            if mover.sector == self.sector || Some(mover.sector) == facing {
+                mover.activate();
+            }
+        }
     }
 }
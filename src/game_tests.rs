@@ -0,0 +1,309 @@
+// Tests for the player simulation in `Game::step`, driven by synthetic key events.
+
+use std::f32::consts::PI;
+
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, NOMOD};
+
+use builder::WorldBuilder;
+use game::{Game, MoveMode};
+use input::{self, InputState};
+use math::{Vec2, V2_ORIGIN};
+use mover::{Mover, Trigger};
+use test_support::{EPSILON, info, rect, assert_near};
+use world::{self, World, SectorIndex, WallIndex};
+
+
+const DT: f32 = 1.0 / 60.0;
+
+
+fn key_down(keycode: Keycode) -> Event {
+    Event::KeyDown { timestamp: 0, window_id: 0, keycode: Some(keycode), scancode: None, keymod: NOMOD, repeat: false }
+}
+
+fn key_up(keycode: Keycode) -> Event {
+    Event::KeyUp { timestamp: 0, window_id: 0, keycode: Some(keycode), scancode: None, keymod: NOMOD, repeat: false }
+}
+
+fn inputs(held: &[Keycode]) -> InputState {
+    let mut inputs = InputState::new(input::default_bindings());
+    for &keycode in held.iter() {
+        inputs.check_event(&key_down(keycode));
+    }
+    inputs
+}
+
+fn run(game: &mut Game, inputs: &InputState, seconds: f32) {
+    for _ in 0..(seconds / DT).round() as usize {
+        game.step(DT, inputs);
+    }
+}
+
+// A tall room on the left and a crawlspace 5 units high on the right.
+fn crawlspace() -> World {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &rect(0.0, 0.0, 10.0, 10.0));
+    builder.add_sector(info(0.0, 5.0), texture, &rect(10.0, 0.0, 20.0, 10.0));
+    builder.build()
+}
+
+// Two rooms 2 units apart with solid walls facing each other across the gap, the right one's
+// floor at `floor_elev`.
+fn gap(floor_elev: f32) -> World {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &rect(0.0, 0.0, 10.0, 10.0));
+    builder.add_sector(info(floor_elev, 20.0), texture, &rect(12.0, 0.0, 22.0, 10.0));
+    builder.build()
+}
+
+// A room on the left and a second one to its right through the portal at x = 10, with a
+// mover in the right one. The player stands in the middle of the left room facing it.
+fn rooms_with_mover(right: (f32, f32), mover: Mover) -> Game {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &rect(0.0, 0.0, 10.0, 10.0));
+    builder.add_sector(info(right.0, right.1), texture, &rect(10.0, 0.0, 20.0, 10.0));
+    let mut world = builder.build();
+    world.add_mover(mover);
+    Game::new(world, SectorIndex(0), Vec2::new(5.0, 5.0), 0.5 * PI)
+}
+
+fn game() -> Game {
+    Game::new(world::temp(), SectorIndex(0), V2_ORIGIN, 0.0)
+}
+
+
+#[test]
+fn standing_player_stays_on_the_floor() {
+    let mut game = game();
+    run(&mut game, &inputs(&[]), 1.0);
+
+    assert!(game.on_ground);
+    assert_eq!(game.z, 0.0);
+    assert_eq!(game.eye_height, game.tuning.eye_height);
+}
+
+#[test]
+fn jump_rises_then_lands() {
+    let mut game = game();
+    let mut held = inputs(&[Keycode::Space]);
+    game.step(DT, &held);
+    held.check_event(&key_up(Keycode::Space));
+
+    assert!(!game.on_ground);
+    assert!(game.z > 0.0);
+
+    // The peak of the arc is v^2 / 2g above the floor.
+    let mut peak: f32 = 0.0;
+    for _ in 0..60 {
+        game.step(DT, &held);
+        peak = peak.max(game.z);
+    }
+    let expected_peak = game.tuning.jump_speed * game.tuning.jump_speed / (2.0 * game.tuning.gravity);
+
+    assert!((peak - expected_peak).abs() < 0.3, "peaked at {}, expected {}", peak, expected_peak);
+    assert!(game.on_ground);
+    assert_eq!(game.z, 0.0);
+    assert_eq!(game.z_vel, 0.0);
+}
+
+#[test]
+fn no_jump_while_airborne() {
+    let mut game = game();
+    game.z = 10.0;
+    game.on_ground = false;
+    game.step(DT, &inputs(&[Keycode::Space]));
+
+    assert!(game.z_vel < 0.0);
+}
+
+#[test]
+fn falling_player_lands_on_the_floor() {
+    let mut game = game();
+    game.z = 10.0;
+    game.on_ground = false;
+
+    game.step(DT, &inputs(&[]));
+    assert!(game.z < 10.0 && game.z_vel < 0.0);
+
+    run(&mut game, &inputs(&[]), 1.0);
+    assert!(game.on_ground);
+    assert_eq!(game.z, 0.0);
+    assert_eq!(game.z_vel, 0.0);
+}
+
+#[test]
+fn crouch_lowers_the_eyes_until_released() {
+    let mut game = game();
+    let mut held = inputs(&[Keycode::LCtrl]);
+    run(&mut game, &held, 1.0);
+
+    assert!((game.eye_height - 2.5).abs() < EPSILON, "eye height {}", game.eye_height);
+    assert!(game.collider.height < 6.0);
+
+    held.check_event(&key_up(Keycode::LCtrl));
+    run(&mut game, &held, 1.0);
+
+    assert_eq!(game.eye_height, game.tuning.eye_height);
+    assert_eq!(game.collider.height, 6.0);
+}
+
+#[test]
+fn stays_crouched_under_low_ceiling() {
+    let mut game = Game::new(crawlspace(), SectorIndex(1), Vec2::new(15.0, 5.0), 0.0);
+    run(&mut game, &inputs(&[]), 1.0);
+
+    assert!((game.eye_height - 2.5).abs() < EPSILON, "eye height {}", game.eye_height);
+    assert!(game.collider.height + game.z <= 5.0);
+
+    // Crawling west into the tall room lets the player stand up again.
+    game.face_angle = -0.5 * PI;
+    run(&mut game, &inputs(&[Keycode::W]), 1.0);
+    run(&mut game, &inputs(&[]), 1.0);
+
+    assert_eq!(game.sector, SectorIndex(0));
+    assert_eq!(game.eye_height, game.tuning.eye_height);
+}
+
+#[test]
+fn noclip_passes_walls_and_finds_its_sector() {
+    // Facing east from the left room, 0.5s at the default speed ends up 9 units on.
+    let mut game = Game::new(gap(0.0), SectorIndex(0), Vec2::new(8.0, 5.0), 0.5 * PI);
+    run(&mut game, &inputs(&[Keycode::W]), 0.5);
+    assert_eq!(game.sector, SectorIndex(0));
+    assert!(game.pos.x < 10.0);
+
+    let mut game = Game::new(gap(0.0), SectorIndex(0), Vec2::new(8.0, 5.0), 0.5 * PI);
+    game.set_move_mode(MoveMode::Noclip);
+    run(&mut game, &inputs(&[Keycode::W]), 0.5);
+    assert_eq!(game.sector, SectorIndex(1));
+    assert_near(game.pos, 17.0, 5.0);
+}
+
+#[test]
+fn noclip_keeps_the_last_sector_between_rooms() {
+    let mut game = Game::new(gap(0.0), SectorIndex(0), Vec2::new(8.0, 5.0), 0.5 * PI);
+    game.set_move_mode(MoveMode::Noclip);
+    run(&mut game, &inputs(&[Keycode::W]), 0.15);
+
+    assert!(game.pos.x > 10.0 && game.pos.x < 12.0, "at x {}", game.pos.x);
+    assert_eq!(game.sector, SectorIndex(0));
+}
+
+#[test]
+fn fly_leaves_the_floor_behind() {
+    let mut game = Game::new(gap(4.0), SectorIndex(0), Vec2::new(8.0, 5.0), 0.5 * PI);
+    game.set_move_mode(MoveMode::Fly);
+
+    run(&mut game, &inputs(&[Keycode::LCtrl]), 0.5);
+    assert!((game.z + 9.0).abs() < EPSILON, "z {}", game.z);
+
+    // No gravity, and no stepping up onto the raised floor of the room it flies into.
+    run(&mut game, &inputs(&[]), 1.0);
+    run(&mut game, &inputs(&[Keycode::W]), 0.5);
+    assert_eq!(game.sector, SectorIndex(1));
+    assert!((game.z + 9.0).abs() < EPSILON, "z {}", game.z);
+    assert!(!game.on_ground);
+}
+
+#[test]
+fn freecam_moves_the_camera_without_the_player() {
+    let mut game = game();
+    game.set_move_mode(MoveMode::Freecam);
+    run(&mut game, &inputs(&[Keycode::W, Keycode::Space]), 0.5);
+
+    assert_near(game.freecam.pos, 0.0, -9.0);
+    assert!((game.freecam.eye_elev - game.tuning.eye_height - 9.0).abs() < EPSILON);
+    assert_near(game.camera(1.0).pos, 0.0, -9.0);
+
+    assert_eq!(game.sector, SectorIndex(0));
+    assert_near(game.pos, 0.0, 0.0);
+    assert_eq!(game.z, 0.0);
+}
+
+#[test]
+fn leaving_freecam_snaps_back_to_the_player() {
+    let mut game = game();
+    game.set_move_mode(MoveMode::Freecam);
+    run(&mut game, &inputs(&[Keycode::W]), 0.5);
+
+    // Freecam is followed by walk. The first frame after switching doesn't interpolate from
+    // where the camera was.
+    game.step(DT, &inputs(&[Keycode::F]));
+    assert_eq!(game.move_mode, MoveMode::Walk);
+    assert_near(game.camera(0.0).pos, 0.0, 0.0);
+    assert!((game.camera(0.0).eye_elev - game.tuning.eye_height).abs() < EPSILON);
+}
+
+#[test]
+fn use_opens_the_door_ahead() {
+    let mut game = rooms_with_mover((0.0, 0.0), Mover::door(SectorIndex(1), 0.0, 12.0));
+    run(&mut game, &inputs(&[Keycode::E]), 0.5);
+
+    assert_eq!(game.world.get_ceiling_elevation(SectorIndex(1)), 12.0);
+}
+
+#[test]
+fn use_ignores_doors_behind_the_player() {
+    let mut game = rooms_with_mover((0.0, 0.0), Mover::door(SectorIndex(1), 0.0, 12.0));
+    game.face_angle = -0.5 * PI;
+    run(&mut game, &inputs(&[Keycode::E]), 0.5);
+
+    assert!(game.movers[0].is_idle());
+    assert_eq!(game.world.get_ceiling_elevation(SectorIndex(1)), 0.0);
+}
+
+#[test]
+fn use_starts_the_lift_being_stood_on() {
+    let mut game = rooms_with_mover((0.0, 20.0), Mover::lift(SectorIndex(1), 0.0, -4.0));
+    run(&mut game, &inputs(&[Keycode::W]), 0.5);
+    assert_eq!(game.sector, SectorIndex(1));
+    assert!(game.movers[0].is_idle());
+
+    run(&mut game, &inputs(&[Keycode::E]), 1.0);
+    assert_eq!(game.world.get_elevation(SectorIndex(1)), -4.0);
+    assert_eq!(game.z, -4.0);
+}
+
+#[test]
+fn walk_trigger_fires_when_its_wall_is_crossed() {
+    let mut lift = Mover::lift(SectorIndex(1), 0.0, -4.0);
+    lift.trigger = Trigger::Walk(SectorIndex(0), WallIndex(1));
+    let mut game = rooms_with_mover((0.0, 20.0), lift);
+
+    run(&mut game, &inputs(&[Keycode::W]), 0.2);
+    assert!(game.movers[0].is_idle());
+
+    run(&mut game, &inputs(&[Keycode::W]), 0.2);
+    assert!(!game.movers[0].is_idle());
+
+    // Use doesn't set off a walk triggered mover.
+    let mut game = rooms_with_mover((0.0, 20.0), lift);
+    run(&mut game, &inputs(&[Keycode::E]), 0.5);
+    assert!(game.movers[0].is_idle());
+}
+
+#[test]
+fn door_reopens_instead_of_closing_on_the_player() {
+    let mut game = rooms_with_mover((0.0, 0.0), Mover::door(SectorIndex(1), 0.0, 12.0));
+    run(&mut game, &inputs(&[Keycode::E]), 0.5);
+    run(&mut game, &inputs(&[Keycode::W]), 0.3);
+    assert_eq!(game.sector, SectorIndex(1));
+
+    let mut lowest = game.world.get_ceiling_elevation(SectorIndex(1));
+    for _ in 0..(5.0 / DT) as usize {
+        game.step(DT, &inputs(&[]));
+        lowest = lowest.min(game.world.get_ceiling_elevation(SectorIndex(1)));
+        assert_eq!(game.z, 0.0);
+    }
+    assert!(lowest >= game.collider.height, "door came down to {}", lowest);
+
+    // Once the player steps back out it closes.
+    run(&mut game, &inputs(&[Keycode::S]), 0.4);
+    assert_eq!(game.sector, SectorIndex(0));
+    run(&mut game, &inputs(&[]), 5.0);
+    assert_eq!(game.world.get_ceiling_elevation(SectorIndex(1)), 0.0);
+    assert!(game.movers[0].is_idle());
+}
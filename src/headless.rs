@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+use sdl2_image::SaveSurface;
+
+use game::Game;
+use math::Vec2;
+use texture::TextureRegistry;
+use world::World;
+
+
+// Renders a single frame into an owned ARGB8888 buffer without touching any
+// SDL window or video subsystem.
+pub fn render_frame(world: &World, sky: &mut Surface, textures: &TextureRegistry, pos: Vec2, face_angle: f32, look_angle: f32, fov: f32, threads: usize, w: usize, h: usize) -> Result<Vec<u8>, String> {
+    let sector = match world.find_sector(pos) {
+        Some(sector) => sector,
+        None => return Err(format!("position ({}, {}) is not inside any sector", pos.x, pos.y)),
+    };
+
+    let mut game = Game::new(world.clone(), sector, pos, face_angle);
+    game.look_angle = look_angle;
+    game.fov = fov;
+    game.render_threads = threads;
+
+    let mut pixels = vec![0u8; 4 * w * h];
+    game.render(sky, textures, &mut pixels, w, h, 1.0);
+    Ok(pixels)
+}
+
+pub fn save_png(pixels: &mut [u8], w: usize, h: usize, path: &Path) -> Result<(), String> {
+    let surface = Surface::from_data(pixels, w as u32, h as u32, 4 * w as u32, PixelFormatEnum::ARGB8888)?;
+    surface.save(path)
+}
@@ -0,0 +1,96 @@
+use font::Font;
+use game::Game;
+use world::SectorIndex;
+
+
+// Seconds a message stays on screen.
+const MESSAGE_TIME: f32 = 3.0;
+// How quickly the displayed frame rate follows the actual one, between 0 and 1.
+const FPS_SMOOTHING: f32 = 0.1;
+
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+pub struct Widget {
+    pub anchor: Anchor,
+    pub color: (u8,u8,u8),
+    _text: Box<dyn Fn(&Game) -> String + Send + Sync>,
+}
+
+// Text drawn over the finished frame. Widgets in the same corner are stacked away from it in
+// the order they were added, messages follow the top left widgets until they time out. Nothing
+// is drawn without a font.
+pub struct Hud {
+    pub font: Option<Font>,
+    pub fps: f32,
+    _widgets: Vec<Widget>,
+    _messages: Vec<(String, f32)>,
+}
+
+
+impl Widget {
+    pub fn text(&self, game: &Game) -> String {
+        (self._text)(game)
+    }
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud {
+            font: None,
+            fps: 0.0,
+            _widgets: vec![],
+            _messages: vec![],
+        }
+    }
+
+    pub fn add_widget<F>(&mut self, anchor: Anchor, color: (u8,u8,u8), text: F)
+    where F: Fn(&Game) -> String + Send + Sync + 'static {
+        self._widgets.push(Widget {
+            anchor: anchor,
+            color: color,
+            _text: Box::new(text),
+        });
+    }
+
+    // The frame rate and the player's position.
+    pub fn add_status_widgets(&mut self) {
+        self.add_widget(Anchor::TopRight, (0xff, 0xff, 0x60), |game| format!("{:.0} FPS", game.hud.fps));
+        self.add_widget(Anchor::BottomLeft, (0xff, 0xff, 0xff), |game| {
+            let SectorIndex(sector) = game.sector;
+            format!("X {:.1} Y {:.1} Z {:.1}\nSECTOR {}", game.pos.x, game.pos.y, game.z, sector)
+        });
+    }
+
+    pub fn get_widgets(&self) -> &[Widget] {
+        &self._widgets
+    }
+
+    pub fn show_message(&mut self, text: &str) {
+        self._messages.push((text.to_string(), MESSAGE_TIME));
+    }
+
+    pub fn get_messages(&self) -> Vec<&str> {
+        self._messages.iter().map(|&(ref text, _)| text.as_str()).collect()
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        for message in self._messages.iter_mut() {
+            message.1 -= dt;
+        }
+        self._messages.retain(|&(_, time_left)| time_left > 0.0);
+    }
+
+    // `frame_time` is the real time taken by the last frame in seconds.
+    pub fn record_frame(&mut self, frame_time: f32) {
+        if frame_time > 0.0 {
+            self.fps += (1.0 / frame_time - self.fps) * FPS_SMOOTHING;
+        }
+    }
+}
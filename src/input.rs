@@ -7,34 +7,105 @@ use sdl2::keyboard::Keycode;
 pub enum Key {
     Left,
     Right,
+    TurnLeft,
+    TurnRight,
     Forward,
     Back,
     ShowMap,
+    Use,
+    Jump,
+    Crouch,
     Quit,
+    MapZoomIn,
+    MapZoomOut,
+    MapPanUp,
+    MapPanDown,
+    MapPanLeft,
+    MapPanRight,
+    MapSeenOnly,
+    MapOverlay,
+    Console,
+    MoveMode,
 }
 
+pub const ALL_KEYS: [Key; 21] = [
+    Key::Forward, Key::Back, Key::Left, Key::Right, Key::TurnLeft, Key::TurnRight,
+    Key::Jump, Key::Crouch, Key::Use, Key::ShowMap, Key::Quit,
+    Key::MapZoomIn, Key::MapZoomOut, Key::MapPanUp, Key::MapPanDown, Key::MapPanLeft, Key::MapPanRight,
+    Key::MapSeenOnly, Key::MapOverlay, Key::Console, Key::MoveMode,
+];
+
 pub struct InputState {
+    _bindings: Vec<(Keycode, Key)>,
     _keys_down: Vec<Key>,
     _mouse_dx: f32,
     _mouse_dy: f32,
 }
 
 
-fn map_sdl_key(key: Keycode) -> Option<Key> {
-    match key {
-        Keycode::W      => Some(Key::Forward),
-        Keycode::S      => Some(Key::Back),
-        Keycode::A      => Some(Key::Left),
-        Keycode::D      => Some(Key::Right),
-        Keycode::Tab    => Some(Key::ShowMap),
-        Keycode::Escape => Some(Key::Quit),
-        _ => None
+pub fn default_bindings() -> Vec<(Keycode, Key)> {
+    vec![
+        (Keycode::W,      Key::Forward),
+        (Keycode::S,      Key::Back),
+        (Keycode::A,      Key::Left),
+        (Keycode::D,      Key::Right),
+        (Keycode::Left,   Key::TurnLeft),
+        (Keycode::Right,  Key::TurnRight),
+        (Keycode::Tab,    Key::ShowMap),
+        (Keycode::E,      Key::Use),
+        (Keycode::Space,  Key::Jump),
+        (Keycode::LCtrl,  Key::Crouch),
+        (Keycode::Escape, Key::Quit),
+        (Keycode::Equals, Key::MapZoomIn),
+        (Keycode::Minus,  Key::MapZoomOut),
+        (Keycode::I,      Key::MapPanUp),
+        (Keycode::K,      Key::MapPanDown),
+        (Keycode::J,      Key::MapPanLeft),
+        (Keycode::L,      Key::MapPanRight),
+        (Keycode::M,      Key::MapSeenOnly),
+        (Keycode::O,      Key::MapOverlay),
+        (Keycode::Backquote, Key::Console),
+        (Keycode::F,      Key::MoveMode),
+    ]
+}
+
+impl Key {
+    // The name used for the key in the settings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::Forward   => "forward",
+            Key::Back      => "back",
+            Key::Left      => "strafe_left",
+            Key::Right     => "strafe_right",
+            Key::TurnLeft  => "turn_left",
+            Key::TurnRight => "turn_right",
+            Key::Jump      => "jump",
+            Key::Crouch    => "crouch",
+            Key::Use       => "use",
+            Key::ShowMap   => "show_map",
+            Key::Quit      => "quit",
+            Key::MapZoomIn   => "map_zoom_in",
+            Key::MapZoomOut  => "map_zoom_out",
+            Key::MapPanUp    => "map_pan_up",
+            Key::MapPanDown  => "map_pan_down",
+            Key::MapPanLeft  => "map_pan_left",
+            Key::MapPanRight => "map_pan_right",
+            Key::MapSeenOnly => "map_seen_only",
+            Key::MapOverlay  => "map_overlay",
+            Key::Console     => "console",
+            Key::MoveMode    => "move_mode",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Key> {
+        ALL_KEYS.iter().cloned().find(|key| key.name() == name)
     }
 }
 
 impl InputState {
-    pub fn new() -> InputState {
+    pub fn new(bindings: Vec<(Keycode, Key)>) -> InputState {
         InputState {
+            _bindings: bindings,
             _keys_down: vec![],
             _mouse_dx: 0.0,
             _mouse_dy: 0.0,
@@ -43,27 +114,39 @@ impl InputState {
 
     pub fn check_event(&mut self, event: &Event) {
         match *event {
-            Event::KeyDown { keycode, .. }  => {
-                if let Some(key) = map_sdl_key(keycode.unwrap()) {
-                    self._keys_down.push(key);
-                    self._keys_down.dedup();
+            Event::KeyDown { keycode: Some(keycode), .. }  => {
+                for &(_, key) in self._bindings.iter().filter(|&&(k, _)| k == keycode) {
+                    if !self._keys_down.contains(&key) {
+                        self._keys_down.push(key);
+                    }
                 }
             }
-            Event::KeyUp { keycode, .. } => {
-                if let Some(key) = map_sdl_key(keycode.unwrap()) {
+            Event::KeyUp { keycode: Some(keycode), .. } => {
+                for &(_, key) in self._bindings.iter().filter(|&&(k, _)| k == keycode) {
                     self._keys_down.retain(|&k| k != key);
                 }
             }
             Event::MouseMotion { xrel, yrel, .. } => {
-                self._mouse_dx = xrel as f32;
-                self._mouse_dy = yrel as f32;
+                self._mouse_dx += xrel as f32;
+                self._mouse_dy += yrel as f32;
             }
             _ => {}
         }
     }
 
+    pub fn binds(&self, keycode: Keycode, key: Key) -> bool {
+        self._bindings.contains(&(keycode, key))
+    }
+
+    // Releases every key, for when key events stop reaching the game.
+    pub fn clear_keys(&mut self) {
+        self._keys_down.clear();
+    }
+
+    // Mouse motion accumulates until cleared, which should happen once per frame.
     pub fn clear_mouse(&mut self) {
-        self._mouse_dx = 0.0
+        self._mouse_dx = 0.0;
+        self._mouse_dy = 0.0;
     }
 
     pub fn mouse_dx(&self) -> f32 {
@@ -1,41 +1,320 @@
 #![allow(dead_code)]
 
 extern crate core;
+extern crate rayon;
 extern crate sdl2;
 extern crate sdl2_image;
+extern crate toml;
 
 mod math;
 mod world;
+mod map;
+mod validate;
+mod builder;
+mod headless;
+mod texture;
+mod sprite;
+mod mover;
 mod game;
 mod input;
 mod render;
+mod automap;
+mod font;
+mod hud;
+mod console;
+mod settings;
+mod resources;
 
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod render_tests;
+#[cfg(test)]
+mod ray_tests;
+#[cfg(test)]
+mod map_tests;
+#[cfg(test)]
+mod validate_tests;
+#[cfg(test)]
+mod builder_tests;
+#[cfg(test)]
+mod collision_tests;
+#[cfg(test)]
+mod game_tests;
+#[cfg(test)]
+mod mover_tests;
+#[cfg(test)]
+mod resources_tests;
+#[cfg(test)]
+mod font_tests;
+#[cfg(test)]
+mod console_tests;
+
+use std::env;
+use std::process;
 use std::thread;
 use std::time::{Instant, Duration};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use sdl2::render::{BlendMode};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::event::Event;
+use sdl2::surface::Surface;
 use sdl2_image::LoadSurface;
 
 
-const WINDOW_WIDTH  :u32 = 3 * 320;
-const WINDOW_HEIGHT :u32 = 3 * 240;
+const FRAME_TIME_MS :u64 = 17;
 
-const W :usize = 320;
-const H :usize = 240;
+// The simulation always advances in steps of STEP_TIME seconds, however long frames take.
+// Frames longer than MAX_FRAME_TIME are clamped so a stall doesn't fast-forward the game.
+const STEP_TIME :f32 = 1.0 / 60.0;
+const MAX_FRAME_TIME :f32 = 0.25;
 
-const FRAME_TIME_MS :u64 = 17;
+const USAGE: &str = "usage: rustycast [map file] [--config <file>] [--res <dir>] [--width <px>] [--height <px>] [--scale <n>] [--fov <degrees>]
+                 [--threads <n>] [--render-frame <out.png> <x> <y> <angle>]";
+
+
+struct Options {
+    map_path: Option<String>,
+    config_path: Option<String>,
+    res_dir: Option<String>,
+    render_frame: Option<FrameRequest>,
+    threads: Option<usize>,
+    width: Option<usize>,
+    height: Option<usize>,
+    scale: Option<u32>,
+    fov: Option<f32>,
+}
+
+struct FrameRequest {
+    out_path: String,
+    pos: math::Vec2,
+    face_angle: f32,
+}
+
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        map_path: None,
+        config_path: None,
+        res_dir: None,
+        render_frame: None,
+        threads: None,
+        width: None,
+        height: None,
+        scale: None,
+        fov: None,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--render-frame" => {
+                if i + 4 >= args.len() {
+                    return Err("--render-frame expects <out.png> <x> <y> <angle>".to_string());
+                }
+                let num = |s: &String| s.parse::<f32>().map_err(|_| format!("invalid number `{}`", s));
+                options.render_frame = Some(FrameRequest {
+                    out_path: args[i + 1].clone(),
+                    pos: math::Vec2::new(num(&args[i + 2])?, num(&args[i + 3])?),
+                    face_angle: num(&args[i + 4])?,
+                });
+                i += 5;
+            }
+            "--config" => {
+                options.config_path = Some(option_value(args, i)?);
+                i += 2;
+            }
+            "--res" => {
+                options.res_dir = Some(option_value(args, i)?);
+                i += 2;
+            }
+            "--threads" => {
+                let threads = option_value(args, i)?;
+                if threads == 0 {
+                    return Err("--threads expects a positive number".to_string());
+                }
+                options.threads = Some(threads);
+                i += 2;
+            }
+            "--width"  => { options.width = Some(option_value(args, i)?);  i += 2; }
+            "--height" => { options.height = Some(option_value(args, i)?); i += 2; }
+            "--scale"  => { options.scale = Some(option_value(args, i)?);  i += 2; }
+            "--fov"    => { options.fov = Some(option_value(args, i)?);    i += 2; }
+            arg if arg.starts_with("--") => return Err(format!("unknown option `{}`", arg)),
+            arg => {
+                options.map_path = Some(arg.to_string());
+                i += 1;
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+// Parses the argument following the option at `args[i]`.
+fn option_value<T: FromStr>(args: &[String], i: usize) -> Result<T, String> {
+    match args.get(i + 1) {
+        Some(value) => value.parse::<T>().map_err(|_| format!("invalid value `{}` for {}", value, args[i])),
+        None => Err(format!("{} expects a value", args[i])),
+    }
+}
+
+// Settings come from the config file, then any given on the command line take precedence.
+// When playing, a missing config file is written out with the defaults so there is something
+// to edit. Rendering a single frame leaves no files behind but the frame.
+fn load_settings(options: &Options) -> Result<settings::Settings, String> {
+    let config_path = options.config_path.as_ref().map_or(settings::DEFAULT_CONFIG_PATH, |p| p.as_str());
+
+    let mut settings = if Path::new(config_path).exists() {
+        settings::Settings::load(Path::new(config_path))
+            .map_err(|err| format!("Failed to load settings from {}: {}", config_path, err))?
+    } else {
+        let settings = settings::Settings::default();
+        if options.render_frame.is_none() {
+            if let Err(err) = settings.save(Path::new(config_path)) {
+                eprintln!("Failed to write default settings to {}: {}", config_path, err);
+            }
+        }
+        settings
+    };
+
+    let video = &mut settings.video;
+    if let Some(width) = options.width { video.width = width; }
+    if let Some(height) = options.height { video.height = height; }
+    if let Some(scale) = options.scale { video.scale = scale; }
+    if let Some(fov) = options.fov { video.fov = fov; }
+    video.check()?;
+
+    Ok(settings)
+}
+
+fn load_textures(world: &world::World, resources: &resources::Resources) -> texture::TextureRegistry {
+    match texture::TextureRegistry::locate(resources, world.get_texture_names()) {
+        Ok(textures) => textures,
+        Err(err) => {
+            eprintln!("Failed to load textures: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+// The game runs without a HUD if there is no font.
+fn load_font(resources: &resources::Resources) -> Option<font::Font> {
+    match resources.find("font.png").and_then(|path| font::Font::load(&path)) {
+        Ok(font) => Some(font),
+        Err(err) => {
+            eprintln!("Failed to load font: {}", err);
+            None
+        }
+    }
+}
+
+fn load_sky<'a>(resources: &resources::Resources) -> Surface<'a> {
+    match resources.find("sky.png").and_then(|path| LoadSurface::from_file(&path)) {
+        Ok(sky) => sky,
+        Err(err) => {
+            eprintln!("Failed to load sky: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+// Runs a line typed into the console, returning what to print.
+fn run_console_line(game: &mut game::Game, textures: &mut texture::TextureRegistry, sky: &mut Surface,
+                    resources: &resources::Resources, line: &str, w: usize, h: usize) -> Result<String, String> {
+    match console::parse_command(game, line)? {
+        console::Command::Map(name) => {
+            let path = resources.find(&format!("maps/{}.map", name))?;
+            let world = world::World::load(&path).map_err(|e| e.to_string())?;
+            let sector = world.find_sector(math::V2_ORIGIN).ok_or(format!("{} has no sector at the origin to start in", path.display()))?;
+            *textures = texture::TextureRegistry::locate(resources, world.get_texture_names())?;
+            game.change_world(world, sector, math::V2_ORIGIN, 0.0);
+            Ok(format!("loaded {}", path.display()))
+        }
+        console::Command::Screenshot(path) => {
+            let path = path.map(PathBuf::from).unwrap_or_else(next_screenshot_path);
+            let mut pixels = vec![0u8; 4 * w * h];
+            game.render_view(sky, textures, &mut pixels, w, h, 1.0);
+            // Parts of the frame the renderer clears are left transparent.
+            for a in pixels.iter_mut().skip(3).step_by(4) {
+                *a = 0xff;
+            }
+            headless::save_png(&mut pixels, w, h, &path)?;
+            Ok(format!("saved {}", path.display()))
+        }
+        command => game.run_command(command),
+    }
+}
+
+// The first of screenshot-1.png, screenshot-2.png, ... which doesn't exist yet.
+fn next_screenshot_path() -> PathBuf {
+    (1..).map(|n| PathBuf::from(format!("screenshot-{}.png", n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+fn seconds(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1.0e-9
+}
 
 
 pub fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(1);
+        }
+    };
+
+    let settings = match load_settings(&options) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    let video = settings.video;
+
+    let world = match options.map_path {
+        Some(map_path) => match world::World::load(Path::new(&map_path)) {
+            Ok(world) => world,
+            Err(err) => {
+                eprintln!("Failed to load map {}: {}", map_path, err);
+                process::exit(1);
+            }
+        },
+        None => world::temp(),
+    };
+
+    let resources = resources::Resources::new(options.res_dir.as_ref().map(Path::new));
+    let threads = options.threads.unwrap_or_else(game::default_render_threads);
+
+    if let Some(frame) = options.render_frame {
+        let mut sky = load_sky(&resources);
+        let textures = load_textures(&world, &resources);
+        let out_path = Path::new(&frame.out_path);
+
+        let result = headless::render_frame(&world, &mut sky, &textures, frame.pos, frame.face_angle, 0.0, video.fov, threads, video.width, video.height)
+            .and_then(|mut pixels| headless::save_png(&mut pixels, video.width, video.height, out_path));
+
+        if let Err(err) = result {
+            eprintln!("Failed to render frame: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
+    // Text events are only wanted while the console is open.
+    let text_input = video_subsystem.text_input();
+    text_input.stop();
 
-    let res_path = Path::new("/Users/jaburns/dev/rustycast/res");
-
-    let window = video_subsystem.window("RustyCast", WINDOW_WIDTH, WINDOW_HEIGHT)
+    let window = video_subsystem.window("RustyCast", video.scale * video.width as u32, video.scale * video.height as u32)
         .position_centered()
         .opengl()
         .build()
@@ -45,21 +324,20 @@ pub fn main() {
 
     let mut renderer = window.renderer().build().unwrap();
 
-    let mut sky = LoadSurface::from_file(&res_path.join("sky.png")).unwrap();
+    let mut sky = load_sky(&resources);
+    let mut textures = load_textures(&world, &resources);
 
-    let mut texture = renderer.create_texture_streaming(PixelFormatEnum::ARGB8888, W as u32, H as u32).unwrap();
+    let mut texture = renderer.create_texture_streaming(PixelFormatEnum::ARGB8888, video.width as u32, video.height as u32).unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let mut inputs = input::InputState::new();
-    let mut game = game::Game {
-        sector: world::SectorIndex(0),
-        pos: math::V2_ORIGIN,
-        face_angle: 0.0,
-        look_angle: 0.0,
-        world: &world::temp(),
-        show_map: false,
-        t: 0.0
-    };
+    let mut inputs = input::InputState::new(settings.bindings.clone());
+    let mut game = game::Game::new(world, world::SectorIndex(0), math::V2_ORIGIN, 0.0);
+    game.render_threads = threads;
+    game.fov = video.fov;
+    game.mouse = settings.mouse;
+    game.automap.overlay = settings.overlay;
+    game.hud.font = load_font(&resources);
+    game.hud.add_status_widgets();
 
     texture.set_blend_mode(BlendMode::None);
 
@@ -67,21 +345,69 @@ pub fn main() {
     // texture.set_alpha_mod(0x22);
     // texture.set_blend_mode(BlendMode::Blend);
 
+    let mut accumulator = 0.0;
+    let mut prev_time = Instant::now();
+
     'main : loop {
         let last_time = Instant::now();
+        let frame_time = seconds(last_time - prev_time);
+        accumulator += frame_time.min(MAX_FRAME_TIME);
+        prev_time = last_time;
+        game.hud.record_frame(frame_time);
 
+        // While the console is open it takes all keyboard input, except the key closing it.
+        let was_open = game.console.open;
         for event in event_pump.poll_iter() {
-            inputs.check_event(&event);
             if let Event::Quit {..} = event { break 'main; }
+
+            if let Event::KeyDown { keycode: Some(keycode), repeat: false, .. } = event {
+                if inputs.binds(keycode, input::Key::Console) {
+                    game.console.toggle();
+                    continue;
+                }
+            }
+
+            if game.console.open {
+                // Text typed before the console opened isn't meant for it.
+                if was_open || !matches!(event, Event::TextInput {..}) {
+                    game.console.check_event(&event);
+                }
+            } else {
+                inputs.check_event(&event);
+            }
+        }
+
+        if game.console.open != text_input.is_active() {
+            if game.console.open {
+                text_input.start();
+                inputs.clear_keys();
+            } else {
+                text_input.stop();
+            }
+        }
+
+        for line in game.console.take_submitted() {
+            match run_console_line(&mut game, &mut textures, &mut sky, &resources, &line, video.width, video.height) {
+                Ok(text) => game.console.print(&text),
+                Err(err) => game.console.print(&format!("error: {}", err)),
+            }
         }
 
         if inputs.has_key(input::Key::Quit) {
             break 'main;
         }
 
-        game.step(&inputs);
+        game.look(&inputs);
+        inputs.clear_mouse();
+
+        while accumulator >= STEP_TIME {
+            game.step(STEP_TIME, &inputs);
+            accumulator -= STEP_TIME;
+        }
+
+        let alpha = accumulator / STEP_TIME;
         texture.with_lock(None, |buffer, _| {
-            game.render(&mut sky, buffer, W, H);
+            game.render(&mut sky, &textures, buffer, video.width, video.height, alpha);
         }).unwrap();
 
         renderer.copy(&texture, None, None);
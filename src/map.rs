@@ -0,0 +1,422 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use math::{LineSeg, Vec2};
+use world::{World, Sector, SectorInfo, SectorIndex, Wall, WallIndex, WallTexture, Peg, Fog};
+use validate::Diagnostic;
+use texture::TextureId;
+use sprite::Sprite;
+use mover::{Mover, Trigger};
+
+
+const DEFAULT_WALL_TEXTURE: &str = "brick";
+const DEFAULT_FLOOR_TEXTURE: &str = "stone";
+const DEFAULT_CEILING_TEXTURE: &str = "panel";
+const DEFAULT_SPRITE_WIDTH: f32 = 3.0;
+const DEFAULT_SPRITE_HEIGHT: f32 = 4.0;
+
+
+pub enum MapError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    Invalid(Vec<Diagnostic>),
+}
+
+// A sprite waiting for every sector to be read so that it can be placed, `line` is where it
+// was declared.
+struct PendingSprite {
+    line: usize,
+    pos: Vec2,
+    texture: TextureId,
+    width: f32,
+    height: f32,
+}
+
+// A door, lift or crusher waiting for its sector's elevations, with the options it was
+// declared with.
+struct PendingMover {
+    line: usize,
+    kind: String,
+    sector: SectorIndex,
+    elev: f32,
+    speed: Option<f32>,
+    wait: Option<f32>,
+    trigger: Trigger,
+}
+
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MapError::Io(ref err) => write!(f, "{}", err),
+            MapError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+            MapError::Invalid(ref diagnostics) => {
+                write!(f, "{} problem(s) found", diagnostics.len())?;
+                for diagnostic in diagnostics.iter() {
+                    write!(f, "\n  {}", diagnostic)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(err: io::Error) -> MapError {
+        MapError::Io(err)
+    }
+}
+
+
+impl World {
+    pub fn load(path: &Path) -> Result<World, MapError> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        contents.parse()
+    }
+}
+
+impl FromStr for World {
+    type Err = MapError;
+
+    fn from_str(s: &str) -> Result<World, MapError> {
+        let mut sectors: Vec<Sector> = vec![];
+        let mut textures: Vec<String> = vec![];
+        let mut sprites: Vec<PendingSprite> = vec![];
+        let mut movers: Vec<PendingMover> = vec![];
+        let mut portals: Vec<(usize, SectorIndex, WallIndex)> = vec![];
+
+        for (i, raw_line) in s.lines().enumerate() {
+            let line = i + 1;
+            let err = |message: String| MapError::Parse { line: line, message: message };
+
+            let content = match raw_line.find('#') {
+                Some(comment) => &raw_line[..comment],
+                None => raw_line,
+            };
+            let tokens: Vec<&str> = content.split_whitespace().collect();
+
+            match tokens.first() {
+                None => {}
+
+                Some(&"sector") => {
+                    if tokens.len() < 3 {
+                        return Err(err("expected `sector <floor> <ceiling> [options]`".to_string()));
+                    }
+                    let floor_elev = parse_num(tokens[1], "floor elevation").map_err(&err)?;
+                    let ceiling_elev = parse_num(tokens[2], "ceiling elevation").map_err(&err)?;
+
+                    let mut floor_tex = DEFAULT_FLOOR_TEXTURE;
+                    let mut ceiling_tex = DEFAULT_CEILING_TEXTURE;
+                    let mut light = 1.0;
+                    let mut fog = None;
+
+                    let mut rest = &tokens[3..];
+                    while !rest.is_empty() {
+                        match rest {
+                            ["floor", name, ..] => {
+                                floor_tex = name;
+                                rest = &rest[2..];
+                            }
+                            ["ceiling", name, ..] => {
+                                ceiling_tex = name;
+                                rest = &rest[2..];
+                            }
+                            ["light", level, ..] => {
+                                light = parse_num(level, "light level").map_err(&err)?;
+                                if light < 0.0 {
+                                    return Err(err(format!("light level must not be negative, found {}", light)));
+                                }
+                                rest = &rest[2..];
+                            }
+                            ["fog", r, g, b, density, ..] => {
+                                let density = parse_num(density, "fog density").map_err(&err)?;
+                                if density <= 0.0 {
+                                    return Err(err(format!("fog density must be positive, found {}", density)));
+                                }
+                                fog = Some(Fog {
+                                    color: (
+                                        parse_channel(r, "fog red").map_err(&err)?,
+                                        parse_channel(g, "fog green").map_err(&err)?,
+                                        parse_channel(b, "fog blue").map_err(&err)?,
+                                    ),
+                                    density: density,
+                                });
+                                rest = &rest[5..];
+                            }
+                            _ => return Err(err(format!("unexpected `{}` in sector", rest[0]))),
+                        }
+                    }
+
+                    sectors.push(Sector {
+                        info: SectorInfo {
+                            floor_elev: floor_elev,
+                            ceiling_elev: ceiling_elev,
+                            floor_tex: intern(&mut textures, floor_tex),
+                            ceiling_tex: intern(&mut textures, ceiling_tex),
+                            light: light,
+                            fog: fog,
+                        },
+                        walls: vec![],
+                    });
+                }
+
+                Some(&"wall") => {
+                    if tokens.len() < 5 {
+                        return Err(err("expected `wall <x0> <y0> <x1> <y1> [options]`".to_string()));
+                    }
+                    let x0 = parse_num(tokens[1], "x0").map_err(&err)?;
+                    let y0 = parse_num(tokens[2], "y0").map_err(&err)?;
+                    let x1 = parse_num(tokens[3], "x1").map_err(&err)?;
+                    let y1 = parse_num(tokens[4], "y1").map_err(&err)?;
+
+                    // Texture names per section (upper, lower, middle). `offset`, `scale` and
+                    // `peg` apply to the sections named by the preceding texture keyword.
+                    let mut names: [Option<&str>; 3] = [None; 3];
+                    let mut slots = [
+                        WallTexture::new(TextureId(0), Peg::Bottom),
+                        WallTexture::new(TextureId(0), Peg::Top),
+                        WallTexture::new(TextureId(0), Peg::Top),
+                    ];
+                    let mut selected: &[usize] = &[0, 1, 2];
+                    let mut portal = None;
+
+                    let mut rest = &tokens[5..];
+                    while !rest.is_empty() {
+                        match rest {
+                            ["texture", name, ..] | ["upper", name, ..] | ["lower", name, ..] | ["middle", name, ..] => {
+                                selected = match rest[0] {
+                                    "upper" => &[0],
+                                    "lower" => &[1],
+                                    "middle" => &[2],
+                                    _ => &[0, 1, 2],
+                                };
+                                for &i in selected.iter() {
+                                    names[i] = Some(name);
+                                }
+                                rest = &rest[2..];
+                            }
+                            ["offset", x, y, ..] => {
+                                let x = parse_num(x, "texture x offset").map_err(&err)?;
+                                let y = parse_num(y, "texture y offset").map_err(&err)?;
+                                for &i in selected.iter() {
+                                    slots[i].offset_x = x;
+                                    slots[i].offset_y = y;
+                                }
+                                rest = &rest[3..];
+                            }
+                            ["scale", scale, ..] => {
+                                let scale = parse_num(scale, "texture scale").map_err(&err)?;
+                                if scale <= 0.0 {
+                                    return Err(err(format!("texture scale must be positive, found {}", scale)));
+                                }
+                                for &i in selected.iter() {
+                                    slots[i].scale = scale;
+                                }
+                                rest = &rest[2..];
+                            }
+                            ["peg", edge, ..] => {
+                                let peg = match *edge {
+                                    "top" => Peg::Top,
+                                    "bottom" => Peg::Bottom,
+                                    _ => return Err(err(format!("expected `peg top` or `peg bottom`, found `{}`", edge))),
+                                };
+                                for &i in selected.iter() {
+                                    slots[i].peg = peg;
+                                }
+                                rest = &rest[2..];
+                            }
+                            ["portal", sector, wall, ..] => {
+                                let sector = parse_index(sector, "portal sector").map_err(&err)?;
+                                let wall = parse_index(wall, "portal wall").map_err(&err)?;
+                                portal = Some((SectorIndex(sector), WallIndex(wall)));
+                                portals.push((line, SectorIndex(sector), WallIndex(wall)));
+                                rest = &rest[3..];
+                            }
+                            _ => return Err(err(format!("unexpected `{}` in wall", rest[0]))),
+                        }
+                    }
+
+                    for i in 0..3 {
+                        slots[i].texture = intern(&mut textures, names[i].unwrap_or(DEFAULT_WALL_TEXTURE));
+                    }
+
+                    let wall = Wall {
+                        seg: LineSeg::new(x0, y0, x1, y1),
+                        portal: portal,
+                        upper: slots[0],
+                        lower: slots[1],
+                        middle: slots[2],
+                    };
+
+                    match sectors.last_mut() {
+                        Some(sector) => sector.walls.push(wall),
+                        None => return Err(err("wall declared before any sector".to_string())),
+                    }
+                }
+
+                Some(&"sprite") => {
+                    if tokens.len() < 4 {
+                        return Err(err("expected `sprite <x> <y> <texture> [size <width> <height>]`".to_string()));
+                    }
+                    let x = parse_num(tokens[1], "x").map_err(&err)?;
+                    let y = parse_num(tokens[2], "y").map_err(&err)?;
+                    let texture = intern(&mut textures, tokens[3]);
+
+                    let (width, height) = match &tokens[4..] {
+                        [] => (DEFAULT_SPRITE_WIDTH, DEFAULT_SPRITE_HEIGHT),
+                        ["size", width, height] => (
+                            parse_num(width, "sprite width").map_err(&err)?,
+                            parse_num(height, "sprite height").map_err(&err)?,
+                        ),
+                        rest => return Err(err(format!("unexpected `{}` in sprite", rest[0]))),
+                    };
+
+                    sprites.push(PendingSprite {
+                        line: line,
+                        pos: Vec2::new(x, y),
+                        texture: texture,
+                        width: width,
+                        height: height,
+                    });
+                }
+
+                Some(&"door") | Some(&"lift") | Some(&"crusher") => {
+                    if tokens.len() < 3 {
+                        return Err(err(format!("expected `{} <sector> <elevation> [options]`", tokens[0])));
+                    }
+                    let sector = parse_index(tokens[1], "mover sector").map_err(&err)?;
+                    let elev = parse_num(tokens[2], "mover elevation").map_err(&err)?;
+
+                    let mut speed = None;
+                    let mut wait = None;
+                    let mut trigger = Trigger::Use;
+
+                    let mut rest = &tokens[3..];
+                    while !rest.is_empty() {
+                        match rest {
+                            ["speed", value, ..] => {
+                                let value = parse_num(value, "mover speed").map_err(&err)?;
+                                if value <= 0.0 {
+                                    return Err(err(format!("mover speed must be positive, found {}", value)));
+                                }
+                                speed = Some(value);
+                                rest = &rest[2..];
+                            }
+                            ["wait", seconds, ..] => {
+                                let seconds = parse_num(seconds, "mover wait").map_err(&err)?;
+                                if seconds < 0.0 {
+                                    return Err(err(format!("mover wait must not be negative, found {}", seconds)));
+                                }
+                                wait = Some(seconds);
+                                rest = &rest[2..];
+                            }
+                            ["walk", trigger_sector, trigger_wall, ..] => {
+                                let trigger_sector = parse_index(trigger_sector, "trigger sector").map_err(&err)?;
+                                let trigger_wall = parse_index(trigger_wall, "trigger wall").map_err(&err)?;
+                                trigger = Trigger::Walk(SectorIndex(trigger_sector), WallIndex(trigger_wall));
+                                rest = &rest[3..];
+                            }
+                            _ => return Err(err(format!("unexpected `{}` in {}", rest[0], tokens[0]))),
+                        }
+                    }
+
+                    movers.push(PendingMover {
+                        line: line,
+                        kind: tokens[0].to_string(),
+                        sector: SectorIndex(sector),
+                        elev: elev,
+                        speed: speed,
+                        wait: wait,
+                        trigger: trigger,
+                    });
+                }
+
+                Some(other) => return Err(err(format!("unknown directive `{}`", other))),
+            }
+        }
+
+        if sectors.is_empty() {
+            return Err(MapError::Parse { line: s.lines().count(), message: "map contains no sectors".to_string() });
+        }
+
+        // Portals may lead to sectors declared further down, so their targets are checked last.
+        for &(line, SectorIndex(sector), WallIndex(wall)) in portals.iter() {
+            if sectors.get(sector).map_or(true, |s| wall >= s.walls.len()) {
+                return Err(MapError::Parse { line: line, message: format!("portal to missing sector {} wall {}", sector, wall) });
+            }
+        }
+
+        let mut world = World::new(sectors, textures);
+
+        // Sprites can only be placed once every sector is complete.
+        for sprite in sprites.iter() {
+            let pos = sprite.pos;
+            match world.find_sector(pos) {
+                Some(sector) => world.add_sprite(Sprite::new(pos, sector, sprite.texture, sprite.width, sprite.height)),
+                None => return Err(MapError::Parse { line: sprite.line, message: format!("sprite at ({}, {}) is not inside any sector", pos.x, pos.y) }),
+            }
+        }
+
+        // Movers start from the elevations their sector was declared with.
+        for pending in movers.iter() {
+            let sector = pending.sector;
+            let info = match world.get_sectors().get(sector.0) {
+                Some(s) => s.info,
+                None => return Err(MapError::Parse { line: pending.line, message: format!("{} refers to missing sector {}", pending.kind, sector.0) }),
+            };
+
+            let mut mover = match pending.kind.as_str() {
+                "door" => Mover::door(sector, info.ceiling_elev, pending.elev),
+                "lift" => Mover::lift(sector, info.floor_elev, pending.elev),
+                _      => Mover::crusher(sector, info.ceiling_elev, pending.elev),
+            };
+            if let Some(speed) = pending.speed { mover.speed = speed; }
+            if let Some(wait) = pending.wait { mover.wait = wait; }
+            mover.trigger = pending.trigger;
+
+            world.add_mover(mover);
+        }
+
+        let diagnostics = world.validate();
+
+        if diagnostics.is_empty() {
+            Ok(world)
+        } else {
+            Err(MapError::Invalid(diagnostics))
+        }
+    }
+}
+
+
+fn parse_num(token: &str, what: &str) -> Result<f32, String> {
+    token.parse().map_err(|_| format!("invalid {} `{}`", what, token))
+}
+
+fn parse_index(token: &str, what: &str) -> Result<usize, String> {
+    token.parse().map_err(|_| format!("invalid {} `{}`", what, token))
+}
+
+fn parse_channel(token: &str, what: &str) -> Result<u8, String> {
+    token.parse().map_err(|_| format!("invalid {} `{}`, expected 0 to 255", what, token))
+}
+
+fn intern(textures: &mut Vec<String>, name: &str) -> TextureId {
+    match textures.iter().position(|t| t == name) {
+        Some(index) => TextureId(index),
+        None => {
+            textures.push(name.to_string());
+            TextureId(textures.len() - 1)
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Tests for the map file parser, checking res/maps/temp.map against `world::temp()` and the
+// line numbers reported for broken maps.
+
+use std::path::Path;
+
+use map::MapError;
+use texture::TextureId;
+use world::{self, World, WallTexture};
+
+
+const EPSILON: f32 = 1.0e-6;
+
+
+fn parse_error(text: &str) -> (usize, String) {
+    match text.parse::<World>() {
+        Ok(_) => panic!("expected a parse error for:\n{}", text),
+        Err(MapError::Parse { line, message }) => (line, message),
+        Err(err) => panic!("expected a parse error, got: {}", err),
+    }
+}
+
+fn texture_name(world: &World, texture: TextureId) -> &str {
+    &world.get_texture_names()[texture.0]
+}
+
+fn assert_same_texture(loaded: &World, expected: &World, a: &WallTexture, b: &WallTexture, what: &str) {
+    assert_eq!(texture_name(loaded, a.texture), texture_name(expected, b.texture), "{} texture", what);
+    assert_eq!((a.offset_x, a.offset_y, a.scale, a.peg), (b.offset_x, b.offset_y, b.scale, b.peg), "{} placement", what);
+}
+
+
+// Texture ids depend on the order names first appear in, so textures are compared by name.
+#[test]
+fn temp_map_matches_temp_world() {
+    let loaded = World::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res/maps/temp.map")).unwrap();
+    let expected = world::temp();
+
+    assert_eq!(loaded.get_sectors().len(), expected.get_sectors().len());
+
+    for (index, (a, b)) in loaded.get_sectors().iter().zip(expected.get_sectors().iter()).enumerate() {
+        let what = format!("sector {}", index);
+        assert_eq!((a.info.floor_elev, a.info.ceiling_elev, a.info.light), (b.info.floor_elev, b.info.ceiling_elev, b.info.light), "{}", what);
+        assert_eq!(a.info.fog.map(|f| (f.color, f.density)), b.info.fog.map(|f| (f.color, f.density)), "{} fog", what);
+        assert_eq!(texture_name(&loaded, a.info.floor_tex), texture_name(&expected, b.info.floor_tex), "{} floor", what);
+        assert_eq!(texture_name(&loaded, a.info.ceiling_tex), texture_name(&expected, b.info.ceiling_tex), "{} ceiling", what);
+        assert_eq!(a.walls.len(), b.walls.len(), "{} walls", what);
+
+        for (wall_index, (wa, wb)) in a.walls.iter().zip(b.walls.iter()).enumerate() {
+            let what = format!("sector {} wall {}", index, wall_index);
+            assert!(wa.seg.a.is_near(wb.seg.a, EPSILON) && wa.seg.b.is_near(wb.seg.b, EPSILON), "{} segment", what);
+            assert_eq!(wa.portal, wb.portal, "{} portal", what);
+            assert_same_texture(&loaded, &expected, &wa.upper, &wb.upper, &format!("{} upper", what));
+            assert_same_texture(&loaded, &expected, &wa.lower, &wb.lower, &format!("{} lower", what));
+            assert_same_texture(&loaded, &expected, &wa.middle, &wb.middle, &format!("{} middle", what));
+        }
+    }
+
+    assert_eq!(loaded.get_sprites().len(), expected.get_sprites().len());
+    for (a, b) in loaded.get_sprites().iter().zip(expected.get_sprites().iter()) {
+        assert!(a.pos.is_near(b.pos, EPSILON));
+        assert_eq!((a.sector, a.width, a.height), (b.sector, b.width, b.height));
+        assert_eq!(texture_name(&loaded, a.texture), texture_name(&expected, b.texture));
+    }
+
+    assert_eq!(loaded.get_movers().len(), expected.get_movers().len());
+    for (a, b) in loaded.get_movers().iter().zip(expected.get_movers().iter()) {
+        assert_eq!((a.sector, a.plane, a.rest_elev, a.active_elev), (b.sector, b.plane, b.rest_elev, b.active_elev));
+        assert_eq!((a.speed, a.wait, a.repeat, a.trigger), (b.speed, b.wait, b.repeat, b.trigger));
+    }
+}
+
+#[test]
+fn bad_number_reports_its_line() {
+    let (line, message) = parse_error("# comment\n\nsector 0 high\n");
+    assert_eq!(line, 3);
+    assert_eq!(message, "invalid ceiling elevation `high`");
+}
+
+#[test]
+fn unknown_option_reports_its_line() {
+    let (line, message) = parse_error("sector 0 20\n    wall 0 0 10 0\n    wall 10 0 0 0 shiny\n");
+    assert_eq!(line, 3);
+    assert_eq!(message, "unexpected `shiny` in wall");
+}
+
+#[test]
+fn unknown_directive_reports_its_line() {
+    let (line, message) = parse_error("sector 0 20\nroom 1 2\n");
+    assert_eq!(line, 2);
+    assert_eq!(message, "unknown directive `room`");
+}
+
+#[test]
+fn missing_portal_target_reports_its_line() {
+    let map = "sector 0 20
+    wall 0 0 10 0
+    wall 10 0 10 10  portal 1 0
+    wall 10 10 0 10
+    wall 0 10 0 0
+sector 0 20
+    wall 10 10 10 0  portal 0 7
+";
+    let (line, message) = parse_error(map);
+    assert_eq!(line, 7);
+    assert_eq!(message, "portal to missing sector 0 wall 7");
+
+    let (line, message) = parse_error(&map.replace("portal 0 7", "portal 0 1").replace("portal 1 0", "portal 2 0"));
+    assert_eq!(line, 3);
+    assert_eq!(message, "portal to missing sector 2 wall 0");
+}
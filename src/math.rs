@@ -121,6 +121,10 @@ impl Vec2 {
     pub fn project(self, rhs: Vec2) -> Vec2 {
         rhs * (self.dot(rhs) / rhs.get_length_sqr())
     }
+
+    pub fn is_near(self, rhs: Vec2, epsilon: f32) -> bool {
+        (self - rhs).get_length_sqr() < epsilon * epsilon
+    }
 }
 
 impl Mat3 {
@@ -177,6 +181,16 @@ impl LineSeg {
         self.a + (self.b - self.a)*t
     }
 
+    pub fn closest_point(self, pt: Vec2) -> Vec2 {
+        let dir = self.b - self.a;
+        let len_sqr = dir.get_length_sqr();
+        if len_sqr == 0.0 {
+            self.a
+        } else {
+            self.at(((pt - self.a).dot(dir) / len_sqr).max(0.0).min(1.0))
+        }
+    }
+
     pub fn transform(self, mat: Mat3) -> LineSeg {
         LineSeg {
             a: mat * self.a,
@@ -184,7 +198,13 @@ impl LineSeg {
         }
     }
 
+    // Returns where the segments cross as a parameter along `rhs`.
     pub fn intersects(self, rhs: LineSeg) -> Option<f32> {
+        self.intersect_params(rhs).map(|(_, t)| t)
+    }
+
+    // Returns where the segments cross as parameters along `self` and along `rhs`.
+    pub fn intersect_params(self, rhs: LineSeg) -> Option<(f32, f32)> {
         let dx1x3 = self.a.x-rhs.a.x;
         let dy1y3 = self.a.y-rhs.a.y;
         let dx2x1 = self.b.x-self.a.x;
@@ -202,7 +222,7 @@ impl LineSeg {
             let nna = numa / denom;
             let nnb = numb / denom;
             if nna >= 0.0 && nna <= 1.0 && nnb >= 0.0 && nnb <= 1.0 {
-                Some(nnb)
+                Some((nna, nnb))
             } else {
                 None
             }
@@ -210,3 +230,11 @@ impl LineSeg {
     }
 }
 
+// Moves `value` towards `target` by at most `step`.
+pub fn approach(value: f32, target: f32, step: f32) -> f32 {
+    if value < target {
+        (value + step).min(target)
+    } else {
+        (value - step).max(target)
+    }
+}
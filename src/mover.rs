@@ -0,0 +1,140 @@
+use world::{World, SectorIndex, WallIndex};
+use math::approach;
+
+
+const DOOR_SPEED: f32 = 30.0;
+const DOOR_WAIT: f32 = 2.0;
+const LIFT_SPEED: f32 = 15.0;
+const LIFT_WAIT: f32 = 1.5;
+const CRUSHER_SPEED: f32 = 9.0;
+
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Plane {
+    Floor,
+    Ceiling,
+}
+
+// `Use` fires when the player presses use while facing a wall into the sector or while
+// standing in it, `Walk` fires when the player crosses the given wall.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Trigger {
+    Use,
+    Walk(SectorIndex, WallIndex),
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum State {
+    Idle,
+    Leaving,
+    Waiting(f32),
+    Returning,
+}
+
+// Moves one plane of a sector from `rest_elev` to `active_elev` and back once triggered.
+// `speed` is in units per second and `wait` is the number of seconds spent at `active_elev`.
+// A repeating mover keeps cycling instead of coming to rest.
+#[derive(Clone,Copy)]
+pub struct Mover {
+    pub sector: SectorIndex,
+    pub plane: Plane,
+    pub rest_elev: f32,
+    pub active_elev: f32,
+    pub speed: f32,
+    pub wait: f32,
+    pub repeat: bool,
+    pub trigger: Trigger,
+    _state: State,
+}
+
+
+impl Mover {
+    pub fn new(sector: SectorIndex, plane: Plane, rest_elev: f32, active_elev: f32, speed: f32, wait: f32, repeat: bool) -> Mover {
+        Mover {
+            sector: sector,
+            plane: plane,
+            rest_elev: rest_elev,
+            active_elev: active_elev,
+            speed: speed,
+            wait: wait,
+            repeat: repeat,
+            trigger: Trigger::Use,
+            _state: State::Idle,
+        }
+    }
+
+    // Raises the ceiling from `closed_elev` to `open_elev`, then closes again.
+    pub fn door(sector: SectorIndex, closed_elev: f32, open_elev: f32) -> Mover {
+        Mover::new(sector, Plane::Ceiling, closed_elev, open_elev, DOOR_SPEED, DOOR_WAIT, false)
+    }
+
+    // Lowers the floor from `top_elev` to `bottom_elev`, then rises again.
+    pub fn lift(sector: SectorIndex, top_elev: f32, bottom_elev: f32) -> Mover {
+        Mover::new(sector, Plane::Floor, top_elev, bottom_elev, LIFT_SPEED, LIFT_WAIT, false)
+    }
+
+    // Lowers the ceiling from `top_elev` to `bottom_elev` and back for as long as the game runs.
+    pub fn crusher(sector: SectorIndex, top_elev: f32, bottom_elev: f32) -> Mover {
+        Mover::new(sector, Plane::Ceiling, top_elev, bottom_elev, CRUSHER_SPEED, 0.0, true)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self._state == State::Idle
+    }
+
+    // Triggering a mover which is already in motion has no effect.
+    pub fn activate(&mut self) {
+        if self._state == State::Idle {
+            self._state = State::Leaving;
+        }
+    }
+
+    // `headroom` is the height of whatever stands in the sector. Rather than close the gap
+    // between floor and ceiling on it, the mover turns back the way it came.
+    pub fn step(&mut self, dt: f32, world: &mut World, headroom: Option<f32>) {
+        let target = match self._state {
+            State::Idle => return,
+            State::Waiting(t) if t <= 0.0 => {
+                self._state = State::Returning;
+                return;
+            }
+            State::Waiting(t) => {
+                self._state = State::Waiting(t - dt);
+                return;
+            }
+            State::Leaving => self.active_elev,
+            State::Returning => self.rest_elev,
+        };
+
+        let info = world.get_sector_info_mut(self.sector);
+        let (floor_elev, ceiling_elev) = (info.floor_elev, info.ceiling_elev);
+        let elev = match self.plane {
+            Plane::Floor => &mut info.floor_elev,
+            Plane::Ceiling => &mut info.ceiling_elev,
+        };
+
+        let next = approach(*elev, target, self.speed * dt);
+        let blocked = match (self.plane, headroom) {
+            (Plane::Floor, Some(height)) => next > *elev && ceiling_elev - next < height,
+            (Plane::Ceiling, Some(height)) => next < *elev && next - floor_elev < height,
+            (_, None) => false,
+        };
+        if blocked {
+            self._state = match self._state {
+                State::Leaving => State::Returning,
+                _ => State::Leaving,
+            };
+            return;
+        }
+
+        *elev = next;
+
+        if *elev == target {
+            self._state = match self._state {
+                State::Leaving => State::Waiting(self.wait),
+                State::Returning if self.repeat => State::Leaving,
+                _ => State::Idle,
+            };
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// Tests for `Mover::step`, moving the planes of a single room through each mover's cycle.
+
+use builder::WorldBuilder;
+use mover::{Mover, Plane};
+use test_support::{info, rect};
+use world::{World, SectorIndex};
+
+
+const DT: f32 = 1.0 / 60.0;
+const ROOM: SectorIndex = SectorIndex(0);
+
+
+fn room(floor_elev: f32, ceiling_elev: f32) -> World {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(floor_elev, ceiling_elev), texture, &rect(0.0, 0.0, 10.0, 10.0));
+    builder.build()
+}
+
+fn elevation(mover: &Mover, world: &World) -> f32 {
+    match mover.plane {
+        Plane::Floor => world.get_elevation(mover.sector),
+        Plane::Ceiling => world.get_ceiling_elevation(mover.sector),
+    }
+}
+
+// Steps the mover for `seconds`, returning the lowest and highest elevation its plane reached.
+fn run(mover: &mut Mover, world: &mut World, seconds: f32, headroom: Option<f32>) -> (f32, f32) {
+    let (mut low, mut high) = (elevation(mover, world), elevation(mover, world));
+    for _ in 0..(seconds / DT).round() as usize {
+        mover.step(DT, world, headroom);
+        low = low.min(elevation(mover, world));
+        high = high.max(elevation(mover, world));
+    }
+    (low, high)
+}
+
+
+#[test]
+fn idle_mover_stays_put() {
+    let mut world = room(0.0, 2.0);
+    let mut door = Mover::door(ROOM, 2.0, 12.0);
+
+    assert_eq!(run(&mut door, &mut world, 1.0, None), (2.0, 2.0));
+    assert!(door.is_idle());
+}
+
+#[test]
+fn door_opens_waits_and_closes() {
+    let mut world = room(0.0, 2.0);
+    let mut door = Mover::door(ROOM, 2.0, 12.0);
+    door.activate();
+
+    // 10 units at 30 per second, then 2 seconds open.
+    run(&mut door, &mut world, 1.0, None);
+    assert_eq!(world.get_ceiling_elevation(ROOM), 12.0);
+    assert!(!door.is_idle());
+
+    run(&mut door, &mut world, 1.5, None);
+    let closing = world.get_ceiling_elevation(ROOM);
+    assert!(closing > 2.0 && closing < 12.0, "door at {}", closing);
+
+    run(&mut door, &mut world, 1.0, None);
+    assert_eq!(world.get_ceiling_elevation(ROOM), 2.0);
+    assert!(door.is_idle());
+}
+
+#[test]
+fn activating_a_moving_door_does_not_restart_it() {
+    let mut world = room(0.0, 2.0);
+    let mut door = Mover::door(ROOM, 2.0, 12.0);
+    door.activate();
+    run(&mut door, &mut world, 2.5, None);
+    let closing = world.get_ceiling_elevation(ROOM);
+
+    door.activate();
+    run(&mut door, &mut world, 0.1, None);
+    assert!(world.get_ceiling_elevation(ROOM) < closing);
+}
+
+#[test]
+fn lift_lowers_waits_and_rises() {
+    let mut world = room(0.0, 20.0);
+    let mut lift = Mover::lift(ROOM, 0.0, -8.0);
+    lift.activate();
+
+    // 8 units at 15 per second, then 1.5 seconds down.
+    run(&mut lift, &mut world, 1.0, None);
+    assert_eq!(world.get_elevation(ROOM), -8.0);
+    assert_eq!(world.get_ceiling_elevation(ROOM), 20.0);
+
+    run(&mut lift, &mut world, 2.0, None);
+    assert_eq!(world.get_elevation(ROOM), 0.0);
+    assert!(lift.is_idle());
+}
+
+#[test]
+fn crusher_keeps_cycling() {
+    let mut world = room(0.0, 20.0);
+    let mut crusher = Mover::crusher(ROOM, 20.0, 4.0);
+    crusher.activate();
+
+    // 16 units at 9 per second each way.
+    assert_eq!(run(&mut crusher, &mut world, 3.6, None), (4.0, 20.0));
+    assert!(!crusher.is_idle());
+
+    let (low, _) = run(&mut crusher, &mut world, 1.0, None);
+    assert!(low < 20.0);
+    assert!(!crusher.is_idle());
+}
+
+#[test]
+fn closing_door_reopens_on_whatever_is_under_it() {
+    let mut world = room(0.0, 2.0);
+    let mut door = Mover::door(ROOM, 2.0, 12.0);
+    door.activate();
+    run(&mut door, &mut world, 1.0, None);
+
+    // It keeps turning back for as long as something 6 units tall stands in the doorway.
+    let (low, high) = run(&mut door, &mut world, 10.0, Some(6.0));
+    assert!(low >= 6.0, "door came down to {}", low);
+    assert_eq!(high, 12.0);
+    assert!(!door.is_idle());
+
+    run(&mut door, &mut world, 5.0, None);
+    assert_eq!(world.get_ceiling_elevation(ROOM), 2.0);
+    assert!(door.is_idle());
+}
+
+#[test]
+fn crusher_turns_back_above_whatever_is_under_it() {
+    let mut world = room(0.0, 20.0);
+    let mut crusher = Mover::crusher(ROOM, 20.0, 4.0);
+    crusher.activate();
+
+    let (low, high) = run(&mut crusher, &mut world, 10.0, Some(6.0));
+    assert!(low >= 6.0, "crusher came down to {}", low);
+    assert_eq!(high, 20.0);
+}
+
+#[test]
+fn rising_lift_stops_short_of_the_ceiling() {
+    let mut world = room(0.0, 5.0);
+    let mut lift = Mover::lift(ROOM, 0.0, -8.0);
+    lift.activate();
+    run(&mut lift, &mut world, 2.0, None);
+
+    // A rider 6 units tall only fits while the floor is at least 6 units below the ceiling.
+    let (_, high) = run(&mut lift, &mut world, 10.0, Some(6.0));
+    assert!(high <= -1.0, "lift rose to {}", high);
+    assert!(!lift.is_idle());
+}
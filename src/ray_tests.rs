@@ -0,0 +1,190 @@
+// Tests for `World::cast_ray`, mostly rays that pass exactly through vertices or run
+// alongside walls where picking the nearest hit is delicate.
+//
+// `ray_cast_benchmark` compares the `RayCast` iterator with the recursive caster it replaced,
+// which collected every hit into a `Vec`. It is ignored by default, run it with
+// `cargo test --release ray_cast_benchmark -- --ignored --nocapture`.
+
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use builder::WorldBuilder;
+use math::{LineSeg, Vec2};
+use test_support::{info, rect, assert_near};
+use texture::TextureId;
+use world::{self, World, Sector, SectorIndex, Wall, WallIndex, RayCastResult};
+
+
+const BENCH_FRAMES: usize = 200;
+const BENCH_COLUMNS: usize = 320;
+const VISPLANE_DIST: f32 = 300.0;
+
+
+fn cast(world: &World, sector: usize, x: f32, y: f32, angle: f32) -> Vec<RayCastResult> {
+    world.cast_ray(SectorIndex(sector), Vec2::new(x, y), angle).collect()
+}
+
+
+// Four sectors around the origin, each with its own floor elevation.
+fn quadrants() -> World {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &rect(-10.0, -10.0,  0.0,  0.0));
+    builder.add_sector(info(1.0, 20.0), texture, &rect(  0.0, -10.0, 10.0,  0.0));
+    builder.add_sector(info(2.0, 20.0), texture, &rect(  0.0,   0.0, 10.0, 10.0));
+    builder.add_sector(info(3.0, 20.0), texture, &rect(-10.0,   0.0,  0.0, 10.0));
+    builder.build()
+}
+
+#[test]
+fn ray_through_vertex_shared_by_portals_reaches_opposite_sector() {
+    let world = quadrants();
+    let hits = cast(&world, 0, -5.0, -5.0, 0.75 * PI);
+    let last = hits.last().unwrap();
+
+    assert!(last.wall.portal.is_none());
+    assert_eq!(last.in_info.floor_elev, 2.0);
+    assert_near(last.hit_pos, 10.0, 10.0);
+}
+
+#[test]
+fn ray_into_corner_next_to_portal_stops_at_corner() {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &rect( 0.0, 0.0, 10.0, 10.0));
+    builder.add_sector(info(1.0, 20.0), texture, &rect(10.0, 0.0, 20.0, 10.0));
+    let world = builder.build();
+
+    let hits = cast(&world, 0, 5.0, 5.0, 0.25 * PI);
+    let last = hits.last().unwrap();
+
+    assert!(last.wall.portal.is_none());
+    assert_near(last.hit_pos, 10.0, 0.0);
+}
+
+#[test]
+fn grazing_ray_hits_far_wall() {
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &rect(0.0, 0.0, 10.0, 10.0));
+    let world = builder.build();
+
+    let hits = cast(&world, 0, 1.0, 9.9999, 0.5 * PI);
+
+    assert_eq!(hits.len(), 1);
+    assert_near(hits[0].hit_pos, 10.0, 9.9999);
+}
+
+#[test]
+fn nearest_wall_wins_at_close_range() {
+    // A U shaped sector, so a ray along the bottom of the U crosses both arms.
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    builder.add_sector(info(0.0, 20.0), texture, &[
+        Vec2::new( 0.0,  0.0), Vec2::new(30.0,  0.0), Vec2::new(30.0, 10.0), Vec2::new(20.0, 10.0),
+        Vec2::new(20.0,  2.0), Vec2::new(10.0,  2.0), Vec2::new(10.0, 10.0), Vec2::new( 0.0, 10.0),
+    ]);
+    let world = builder.build();
+
+    let hits = cast(&world, 0, 9.95, 5.0, 0.5 * PI);
+
+    assert_eq!(hits.len(), 1);
+    assert_near(hits[0].hit_pos, 10.0, 5.0);
+}
+
+#[test]
+fn portal_cycle_is_cut_off() {
+    // The east and west walls lead into each other, so a ray heading east never stops.
+    let world = World::new(vec![
+        Sector {
+            info: info(0.0, 20.0),
+            walls: vec![
+                Wall::new( 0.0,  0.0, 10.0,  0.0, TextureId(0), None),
+                Wall::new(10.0,  0.0, 10.0, 10.0, TextureId(0), Some((SectorIndex(0), WallIndex(3)))),
+                Wall::new(10.0, 10.0,  0.0, 10.0, TextureId(0), None),
+                Wall::new( 0.0, 10.0,  0.0,  0.0, TextureId(0), Some((SectorIndex(0), WallIndex(1)))),
+            ],
+        },
+    ], vec!["brick".to_string()]);
+
+    let hits = world.cast_ray(SectorIndex(0), Vec2::new(5.0, 5.0), 0.5 * PI).take(1000).count();
+
+    assert!(hits > 1 && hits < 1000, "{} hits", hits);
+}
+
+
+// The caster as it was before `RayCast`, kept as the benchmark's baseline. It has no guard
+// against portal cycles, which `world::temp()` doesn't have.
+fn recursive_cast_ray(world: &World, sector: SectorIndex, source_wall: Option<WallIndex>, pos: Vec2, angle: f32, results: &mut Vec<RayCastResult>) {
+    let ray = LineSeg::new(
+        pos.x, pos.y,
+        pos.x + 1000.0*angle.sin(),
+        pos.y - 1000.0*angle.cos()
+    );
+
+    let SectorIndex(sec_index) = sector;
+    let sectors = world.get_sectors();
+
+    let closest_wall = sectors[sec_index].walls.iter().enumerate()
+        .filter(|&(i, _)| source_wall.is_none() || WallIndex(i) != source_wall.unwrap())
+        .filter_map(|(i, wall)| ray.intersects(wall.seg).map(|t| (i, wall, t)))
+        .min_by_key(|&(_, wall, t)| {
+            let d2 = (pos - wall.seg.at(t)).get_length_sqr();
+            if d2 < 0.01 { 1000000 } else { (d2 * 100.0) as i32 }
+        });
+
+    let (wall_index, wall, t) = match closest_wall {
+        Some(closest) => closest,
+        None => return,
+    };
+
+    results.push(RayCastResult {
+        along: wall.seg.get_length()*t,
+        sector: sector,
+        wall_index: WallIndex(wall_index),
+        wall: *wall,
+        hit_pos: wall.seg.at(t),
+        in_info: sectors[sec_index].info,
+        out_info: wall.portal.map(|(SectorIndex(sec), _)| sectors[sec].info),
+    });
+
+    if let Some((next_sector, next_wall)) = wall.portal {
+        recursive_cast_ray(world, next_sector, Some(next_wall), wall.seg.at(t), angle, results);
+    }
+}
+
+// Sweeps a full screen's worth of columns while turning on the spot, like render_game does,
+// and returns the number of hits and the time taken in milliseconds.
+fn sweep<F: FnMut(f32) -> usize>(mut cast: F) -> (usize, f64) {
+    let start = Instant::now();
+    let mut hits = 0;
+
+    for frame in 0..BENCH_FRAMES {
+        let face_angle = frame as f32 * 0.03;
+        for x in 0..BENCH_COLUMNS {
+            let offset_angle = ((x as f32 - BENCH_COLUMNS as f32 / 2.0) / VISPLANE_DIST).atan();
+            hits += cast(face_angle + offset_angle);
+        }
+    }
+
+    let elapsed = Instant::now() - start;
+    (hits, elapsed.as_secs() as f64 * 1.0e3 + elapsed.subsec_nanos() as f64 * 1.0e-6)
+}
+
+#[test]
+#[ignore]
+fn ray_cast_benchmark() {
+    let world = world::temp();
+    let casts = BENCH_FRAMES * BENCH_COLUMNS;
+
+    let (old_hits, old_millis) = sweep(|angle| {
+        let mut results = vec![];
+        recursive_cast_ray(&world, SectorIndex(0), None, Vec2::new(0.0, 0.0), angle, &mut results);
+        results.len()
+    });
+    let (hits, millis) = sweep(|angle| world.cast_ray(SectorIndex(0), Vec2::new(0.0, 0.0), angle).count());
+
+    println!("{} casts", casts);
+    println!("recursive: {} hits in {:.2}ms ({:.3}us per cast)", old_hits, old_millis, old_millis * 1.0e3 / casts as f64);
+    println!("RayCast:   {} hits in {:.2}ms ({:.3}us per cast)", hits, millis, millis * 1.0e3 / casts as f64);
+}
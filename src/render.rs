@@ -1,78 +1,458 @@
+use std::sync::Mutex;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use sdl2::surface::Surface;
 
-use world::{RayCastResult};
-use math::{LineSeg, Vec2, Mat3};
-use game::{Game};
+use world::{RayCastResult, Sector, SectorInfo, SectorIndex, Wall, WallIndex, WallTexture, Peg};
+use math::{LineSeg, Vec2, Mat3, V2_ORIGIN};
+use game::{Game, Camera};
+use texture::{Texture, TextureRegistry};
+use sprite::{Sprite, DepthClip};
+use automap::OverlayMode;
+use font::{Font, Align};
+use hud::Anchor;
 use core::ops::Range;
 
-const MAP_SCALE: f32 = 2.0;
-const VISPLANE_DIST: f32 = 300.0;
-const PERSON_HEIGHT: f32 = 5.0;
-
-
-struct RenderContext<'a> {
+const TEXELS_PER_UNIT: f32 = 4.0;
+const SPRITE_NEAR_CLIP: f32 = 0.5;
+
+const MAP_SOLID: (u8,u8,u8) = (0xff, 0x00, 0x00);
+const MAP_STEP: (u8,u8,u8) = (0xff, 0xd0, 0x40);
+const MAP_PORTAL: (u8,u8,u8) = (0x50, 0x50, 0x50);
+const MAP_PLAYER: (u8,u8,u8) = (0xff, 0xff, 0xff);
+// The corner overlay covers a third of the screen each way, inset from the top right.
+const OVERLAY_CORNER_DIVISOR: usize = 3;
+const OVERLAY_MARGIN: usize = 4;
+
+const HUD_MARGIN: isize = 2;
+const HUD_MESSAGE_COLOR: (u8,u8,u8) = (0xff, 0xff, 0xff);
+const HUD_SHADOW_COLOR: (u8,u8,u8) = (0x00, 0x00, 0x00);
+// The open console covers the top half of the screen, darkening what is behind it.
+const CONSOLE_SHADE: f32 = 0.75;
+const CONSOLE_TEXT_COLOR: (u8,u8,u8) = (0xc0, 0xc0, 0xc0);
+const CONSOLE_INPUT_COLOR: (u8,u8,u8) = (0xff, 0xff, 0xff);
+// The player's arrow as line segments around its position, pointing up the screen.
+const MAP_ARROW: [(f32,f32,f32,f32); 4] = [
+    ( 0.0, -6.0, -4.0,  4.0),
+    ( 0.0, -6.0,  4.0,  4.0),
+    (-4.0,  4.0,  0.0,  2.0),
+    ( 4.0,  4.0,  0.0,  2.0),
+];
+
+
+// `pixels` holds just the band of `columns` out of a `width` by `height` frame, drawing
+// functions take frame coordinates. `visplane_dist` is the distance in pixels from the eye
+// to the screen which gives the field of view.
+pub struct RenderContext<'a> {
     pub pixels: &'a mut [u8],
     pub width: isize,
     pub height: isize,
+    pub columns: Range<usize>,
+    pub visplane_dist: f32,
+}
+
+// Worker threads and band buffers kept from one frame to the next, so that rendering on
+// several threads doesn't start threads or allocate every frame. They are rebuilt when the
+// number of threads changes.
+pub struct RenderPool {
+    _state: Mutex<PoolState>,
+}
+
+// `clip` is for rendering on the calling thread, `overlay` holds the map before it is
+// blended into the frame.
+struct PoolState {
+    workers: Option<ThreadPool>,
+    bands: Vec<Band>,
+    clip: DepthClip,
+    overlay: Vec<u8>,
+}
+
+// A band of columns and the buffers it is rendered with.
+struct Band {
+    columns: Range<usize>,
+    pixels: Vec<u8>,
+    clip: DepthClip,
+}
+
+// The rows from `top` to `bottom` of screen column `x`, before clipping to the frame.
+#[derive(Clone, Copy)]
+pub struct ColumnSpan {
+    pub x: usize,
+    pub top: isize,
+    pub bottom: isize,
+}
+
+// Texture coordinates down a column, V is `v_top + y * v_step` at screen row `y`.
+#[derive(Clone, Copy)]
+pub struct TexSpan {
+    pub u: f32,
+    pub v_top: f32,
+    pub v_step: f32,
+}
+
+// Light and fog for a surface `dist` away in the given sector.
+#[derive(Clone, Copy)]
+pub struct Shading<'a> {
+    pub info: &'a SectorInfo,
+    pub dist: f32,
+}
+
+// A ray from the eye at `pos` through `hit_pos`, for finding where each row of a floor or
+// ceiling `elevation` below the eye lies. `cos_angle` is the cosine of the ray's angle off the
+// view direction and `look` the row offset of looking up or down.
+#[derive(Clone, Copy)]
+pub struct FlatRay {
+    pub elevation: f32,
+    pub pos: Vec2,
+    pub hit_pos: Vec2,
+    pub cos_angle: f32,
+    pub look: isize,
+}
+
+// How world heights along a ray project onto the screen, `horizon` is the screen row at eye level.
+struct Projection {
+    eye_height: f32,
+    horizon: isize,
+    cast_dist: f32,
+    visplane_dist: f32,
+}
+
+// The locked sky surface, stretched over the whole frame whatever its size.
+pub struct Sky<'a> {
+    pub pixels: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+}
+
+
+impl RenderPool {
+    pub fn new() -> RenderPool {
+        RenderPool {
+            _state: Mutex::new(PoolState {
+                workers: None,
+                bands: vec![],
+                clip: DepthClip::new(0..0, 0),
+                overlay: vec![],
+            }),
+        }
+    }
+}
+
+impl PoolState {
+    // Matches the workers and bands to the thread count and frame size, keeping the buffers'
+    // memory. Returns false if the worker threads couldn't be started.
+    fn prepare(&mut self, threads: usize, w: usize, h: usize) -> bool {
+        if self.workers.as_ref().map(|workers| workers.current_num_threads()) != Some(threads) {
+            self.workers = ThreadPoolBuilder::new().num_threads(threads).build().ok();
+        }
+
+        let band_width = w.div_ceil(threads);
+        let count = w.div_ceil(band_width);
+        self.bands.resize_with(count, || Band { columns: 0..0, pixels: vec![], clip: DepthClip::new(0..0, 0) });
+
+        for (i, band) in self.bands.iter_mut().enumerate() {
+            band.columns = i * band_width .. ((i + 1) * band_width).min(w);
+            band.pixels.resize(4 * band.columns.len() * h, 0);
+        }
+
+        self.workers.is_some()
+    }
 }
 
+impl Game {
+    // `alpha` is how far the frame lies between the previous and the current step.
+    pub fn render(&self, sky: &mut Surface, textures: &TextureRegistry, pixels: &mut [u8], w :usize, h: usize, alpha: f32) {
+        self.render_view(sky, textures, pixels, w, h, alpha);
 
-impl<'a> Game<'a> {
-    pub fn render(&self, sky: &mut Surface, pixels: &mut [u8], w :usize, h: usize) {
         let mut ctx = RenderContext {
             pixels: pixels,
             width: w as isize,
             height: h as isize,
+            columns: 0..w,
+            visplane_dist: 0.0,
         };
+        self.render_console(&mut ctx);
+    }
+
+    // The frame without the console over it, as saved by screenshots.
+    pub fn render_view(&self, sky: &mut Surface, textures: &TextureRegistry, pixels: &mut [u8], w :usize, h: usize, alpha: f32) {
+        let cam = self.camera(alpha);
+        // Worked out in double precision so the default FOV lands on exactly 300 pixels.
+        let visplane_dist = (w as f64 / 2.0 / (self.fov as f64 / 2.0).to_radians().tan()) as f32;
 
         if self.show_map {
-            self.render_map(&mut ctx);
-        } else {
-            self.render_game(sky, &mut ctx);
+            let mut ctx = RenderContext {
+                pixels: pixels,
+                width: w as isize,
+                height: h as isize,
+                columns: 0..w,
+                visplane_dist: visplane_dist,
+            };
+            self.render_map(&cam, &mut ctx);
+            self.render_hud(&mut ctx);
+            return;
+        }
+
+        let threads = self.render_threads.max(1).min(w);
+        let (sky_width, sky_height) = (sky.width() as usize, sky.height() as usize);
+        let mut pool = self.render_pool._state.lock().unwrap();
+
+        sky.with_lock(|sky_pixels| {
+            let sky = Sky {
+                pixels: sky_pixels,
+                width: sky_width,
+                height: sky_height,
+            };
+
+            let mut ctx = RenderContext {
+                pixels: pixels,
+                width: w as isize,
+                height: h as isize,
+                columns: 0..w,
+                visplane_dist: visplane_dist,
+            };
+
+            if threads > 1 && pool.prepare(threads, w, h) {
+                self.render_game_bands(&cam, &sky, textures, &mut pool, &mut ctx);
+            } else {
+                self.render_game(&cam, &sky, textures, &mut ctx, &mut pool.clip);
+            }
+        });
+
+        self.render_overlay(&cam, &mut pool.overlay, pixels, w, h);
+
+        let mut ctx = RenderContext {
+            pixels: pixels,
+            width: w as isize,
+            height: h as isize,
+            columns: 0..w,
+            visplane_dist: visplane_dist,
+        };
+        self.render_hud(&mut ctx);
+    }
+
+    // Widgets and messages, each with a drop shadow so they stay readable on any background.
+    fn render_hud(&self, ctx: &mut RenderContext) {
+        let font = match self.hud.font {
+            Some(ref font) => font,
+            None => return,
+        };
+
+        let widgets = self.hud.get_widgets().iter()
+            .map(|widget| (widget.anchor, widget.color, widget.text(self)));
+        let messages = self.hud.get_messages().into_iter()
+            .map(|message| (Anchor::TopLeft, HUD_MESSAGE_COLOR, message.to_string()));
+
+        // How far each corner's stack of text already reaches from its edge.
+        let mut stacked = [0isize; 4];
+
+        for (anchor, color, text) in widgets.chain(messages) {
+            let height = font.text_height(&text) as isize;
+            let offset = &mut stacked[anchor as usize];
+
+            let (x, align) = match anchor {
+                Anchor::TopLeft | Anchor::BottomLeft => (HUD_MARGIN, Align::Left),
+                Anchor::TopRight | Anchor::BottomRight => (ctx.width - HUD_MARGIN, Align::Right),
+            };
+            let y = match anchor {
+                Anchor::TopLeft | Anchor::TopRight => HUD_MARGIN + *offset,
+                Anchor::BottomLeft | Anchor::BottomRight => ctx.height - HUD_MARGIN - *offset - height,
+            };
+            *offset += height;
+
+            ctx.draw_text(font, &text, x + 1, y + 1, align, HUD_SHADOW_COLOR);
+            ctx.draw_text(font, &text, x, y, align, color);
+        }
+    }
+
+    // Slides down from the top of the screen with the input line along its bottom edge and
+    // the newest output just above it.
+    fn render_console(&self, ctx: &mut RenderContext) {
+        let font = match self.hud.font {
+            Some(ref font) => font,
+            None => return,
+        };
+        let slide = self.console.get_slide();
+        if slide <= 0.0 { return; }
+
+        let bottom = ((ctx.height / 2) as f32 * slide) as isize;
+        for i in 0..4*(ctx.width*bottom) as usize {
+            if i % 4 != 3 {
+                ctx.pixels[i] = blend(ctx.pixels[i], 0x00, CONSOLE_SHADE);
+            }
+        }
+
+        let line_height = font.glyph_height as isize;
+        let mut y = bottom - HUD_MARGIN - line_height;
+        ctx.draw_text(font, &format!("> {}_", self.console.get_input()), HUD_MARGIN, y, Align::Left, CONSOLE_INPUT_COLOR);
+
+        for line in self.console.get_lines().iter().rev() {
+            y -= line_height;
+            if y + line_height <= 0 { break; }
+            ctx.draw_text(font, line, HUD_MARGIN, y, Align::Left, CONSOLE_TEXT_COLOR);
         }
     }
 
-    fn render_map(&self, ctx: &mut RenderContext) {
-        let trans = Mat3::rotation(-self.face_angle)
-                  * Mat3::translation(-self.pos * MAP_SCALE)
-                  * Mat3::scale(Vec2::new(MAP_SCALE, MAP_SCALE));
+    // Columns don't depend on each other, so each worker renders a band of them into its
+    // own buffer which is then copied into the frame. The output is identical to rendering
+    // the whole frame on one thread. `frame` covers every column.
+    fn render_game_bands(&self, cam: &Camera, sky: &Sky, textures: &TextureRegistry, pool: &mut PoolState, frame: &mut RenderContext) {
+        let PoolState { ref workers, ref mut bands, .. } = *pool;
+        let workers = match *workers {
+            Some(ref workers) => workers,
+            None => return,
+        };
+        let (width, height, visplane_dist) = (frame.width, frame.height, frame.visplane_dist);
+
+        workers.scope(|scope| {
+            for band in bands.iter_mut() {
+                scope.spawn(move |_| {
+                    let mut ctx = RenderContext {
+                        pixels: &mut band.pixels,
+                        width: width,
+                        height: height,
+                        columns: band.columns.clone(),
+                        visplane_dist: visplane_dist,
+                    };
+                    self.render_game(cam, sky, textures, &mut ctx, &mut band.clip);
+                });
+            }
+        });
+
+        let w = width as usize;
+        for band in bands.iter() {
+            let row_bytes = 4 * band.columns.len();
+            for y in 0..height as usize {
+                frame.pixels[4*(w*y + band.columns.start) .. 4*(w*y + band.columns.end)].copy_from_slice(&band.pixels[row_bytes*y .. row_bytes*(y+1)]);
+            }
+        }
+    }
 
+    fn render_map(&self, cam: &Camera, ctx: &mut RenderContext) {
         ctx.clear(0x00);
-        let (player_x, player_y) = (ctx.width / 2, ctx.height / 2);
-        ctx.put_px(player_x as usize, player_y as usize, 0xFF, 0x00, 0x00);
+        self.draw_map(cam, ctx, self.automap.scale, self.automap.pan);
+    }
+
+    // Blends the map into the finished frame. Map lines are mixed in at the overlay's opacity,
+    // and in the corner the scene behind the map is also darkened by that much.
+    fn render_overlay(&self, cam: &Camera, map_pixels: &mut Vec<u8>, pixels: &mut [u8], w: usize, h: usize) {
+        let overlay = self.automap.overlay;
+        let (left, top, overlay_w, overlay_h) = match overlay.mode {
+            OverlayMode::Off => return,
+            OverlayMode::Corner => {
+                let (overlay_w, overlay_h) = (w / OVERLAY_CORNER_DIVISOR, h / OVERLAY_CORNER_DIVISOR);
+                if overlay_w + OVERLAY_MARGIN > w || overlay_h + OVERLAY_MARGIN > h { return; }
+                (w - overlay_w - OVERLAY_MARGIN, OVERLAY_MARGIN, overlay_w, overlay_h)
+            }
+            OverlayMode::Full => (0, 0, w, h),
+        };
+
+        // The map is drawn onto a cleared buffer, so only drawn pixels have a non-zero alpha.
+        map_pixels.clear();
+        map_pixels.resize(4 * overlay_w * overlay_h, 0);
+        {
+            let mut ctx = RenderContext {
+                pixels: map_pixels,
+                width: overlay_w as isize,
+                height: overlay_h as isize,
+                columns: 0..overlay_w,
+                visplane_dist: 0.0,
+            };
+            self.draw_map(cam, &mut ctx, overlay.scale, V2_ORIGIN);
+        }
+
+        let darken_background = overlay.mode == OverlayMode::Corner;
+
+        for y in 0..overlay_h {
+            for x in 0..overlay_w {
+                let src = 4*(overlay_w*y + x);
+                let dst = 4*(w*(top + y) + left + x);
+                if map_pixels[src + 3] == 0 && !darken_background { continue; }
+
+                for c in 0..3 {
+                    pixels[dst + c] = blend(pixels[dst + c], map_pixels[src + c], overlay.opacity);
+                }
+            }
+        }
+    }
+
+    // The map turns with the player so that straight ahead is always up the screen.
+    fn draw_map(&self, cam: &Camera, ctx: &mut RenderContext, scale: f32, pan: Vec2) {
+        let map = &self.automap;
+        let player = -pan * scale;
+        let trans = Mat3::translation(player)
+                  * Mat3::rotation(-cam.face_angle)
+                  * Mat3::translation(-cam.pos * scale)
+                  * Mat3::scale(Vec2::new(scale, scale));
+
+        for (sec_index, sector) in self.world.get_sectors().iter().enumerate() {
+            for (wall_index, wall) in sector.walls.iter().enumerate() {
+                if map.seen_only && !map.is_seen(SectorIndex(sec_index), WallIndex(wall_index)) {
+                    continue;
+                }
+                let (r, g, b) = self.map_color(sector, wall);
+                ctx.draw_seg(wall.seg.transform(trans), r, g, b);
+            }
+        }
 
-        for wall in self.world.get_walls().iter() {
-            ctx.draw_seg(wall.seg.transform(trans), 0xFF, 0x00, 0x00);
+        let (r, g, b) = MAP_PLAYER;
+        for &(x0, y0, x1, y1) in MAP_ARROW.iter() {
+            ctx.draw_seg(LineSeg::new(player.x + x0, player.y + y0, player.x + x1, player.y + y1), r, g, b);
         }
     }
 
-    fn render_game(&self, sky: &mut Surface, ctx: &mut RenderContext) {
-        let person_height = PERSON_HEIGHT + self.world.get_elevation(self.sector); //+ Float::abs(Float::sin(self.t * 3.0)) * 10.0;
-        let looking_offset = -self.look_angle as isize;
+    // Portals are only highlighted where the floor or ceiling changes height.
+    fn map_color(&self, sector: &Sector, wall: &Wall) -> (u8,u8,u8) {
+        match wall.portal {
+            None => MAP_SOLID,
+            Some((SectorIndex(other), _)) => {
+                let other = self.world.get_sectors()[other].info;
+                if other.floor_elev != sector.info.floor_elev || other.ceiling_elev != sector.info.ceiling_elev {
+                    MAP_STEP
+                } else {
+                    MAP_PORTAL
+                }
+            }
+        }
+    }
+
+    fn render_game(&self, cam: &Camera, sky: &Sky, textures: &TextureRegistry, ctx: &mut RenderContext, clip: &mut DepthClip) {
+        let person_height = cam.eye_elev; //+ Float::abs(Float::sin(self.t * 3.0)) * 10.0;
+        let looking_offset = -cam.look_angle as isize;
         let w = ctx.width as usize;
         let h = ctx.height as usize;
+        let visplane_dist = ctx.visplane_dist;
+        clip.reset(ctx.columns.clone(), h);
 
         //ctx.clear(0xff);
-        for x in 0..w {
+        for x in ctx.columns.clone() {
             let offset_pos = (x as f32) - (w as f32) / 2.0;
-            let offset_angle = (offset_pos / VISPLANE_DIST).atan();
+            let offset_angle = (offset_pos / visplane_dist).atan();
             let cos_offset = offset_angle.cos();
 
             let mut render_bottom = h as isize;
             let mut render_top = 0;
 
-            for RayCastResult {along, hit_pos, in_info, out_info}
-            in self.world.cast_ray(self.sector, self.pos, self.face_angle + offset_angle) {
-                let dist = (hit_pos - self.pos).get_length();
+            for RayCastResult {along, sector, wall_index, wall, hit_pos, in_info, out_info}
+            in self.world.cast_ray(cam.sector, cam.pos, cam.face_angle + offset_angle) {
+                self.automap.mark_seen(sector, wall_index);
+                if let Some((other_sector, other_wall)) = wall.portal {
+                    self.automap.mark_seen(other_sector, other_wall);
+                }
+
+                let dist = (hit_pos - cam.pos).get_length();
                 let cast_dist = dist * cos_offset;
                 let middle = h as isize / 2 + looking_offset;
+                let proj = Projection { eye_height: person_height, horizon: middle, cast_dist: cast_dist, visplane_dist: visplane_dist };
+                let shading = Shading { info: &in_info, dist: cast_dist };
 
-                let floor_wall_seg_height = match out_info {
-                    Some(i) => i.floor_elev - in_info.floor_elev,
-                    None    => in_info.ceiling_elev - in_info.floor_elev,
+                let (floor_wall_tex, floor_wall_top_elev) = match out_info {
+                    Some(i) => (wall.lower, i.floor_elev),
+                    None    => (wall.middle, in_info.ceiling_elev),
                 };
+                let floor_wall_seg_height = floor_wall_top_elev - in_info.floor_elev;
                 let floor_wall_seg_height_px = if floor_wall_seg_height > 0.0 {
-                    (VISPLANE_DIST * floor_wall_seg_height / cast_dist) as isize
+                    (visplane_dist * floor_wall_seg_height / cast_dist) as isize
                 } else {
                     0
                 };
@@ -82,48 +462,103 @@ impl<'a> Game<'a> {
                     None    => 0.0
                 };
                 let ceiling_wall_seg_height_px = if ceiling_wall_seg_height > 0.0 {
-                    (VISPLANE_DIST * ceiling_wall_seg_height / cast_dist) as isize
+                    (visplane_dist * ceiling_wall_seg_height / cast_dist) as isize
                 } else {
                     0
                 };
 
-                // TODO Before drawing the flats and walls for this sector, let's
-                // clip the sprite renders with what's already been drawn.
-                // SHould have a second buffer to hold sprite renders which is blt'd
-                // to the main buffer after all the raycasting is done.
-
-                let floor_wall_bottom = middle + (VISPLANE_DIST * (person_height - in_info.floor_elev) / cast_dist) as isize;
+                let floor_wall_bottom = middle + (visplane_dist * (person_height - in_info.floor_elev) / cast_dist) as isize;
                 let floor_wall_top = floor_wall_bottom - floor_wall_seg_height_px;
-                let floor_wall_offset_bottom = if floor_wall_bottom > render_bottom { floor_wall_bottom - render_bottom } else { 0 };
 
                 let draw_floor_wall_top = if floor_wall_top < render_top { render_top } else { floor_wall_top };
                 let draw_floor_wall_bottom = if floor_wall_bottom > render_bottom { render_bottom } else { floor_wall_bottom };
 
-                ctx.draw_wall(x, draw_floor_wall_top, draw_floor_wall_bottom, floor_wall_offset_bottom, along, cast_dist);
+                let tex = wall_tex_coords(&floor_wall_tex, along, floor_wall_top_elev, in_info.floor_elev, &proj);
+                ctx.draw_wall(ColumnSpan { x: x, top: draw_floor_wall_top, bottom: draw_floor_wall_bottom }, textures.get(floor_wall_tex.texture), tex, shading);
 
-                let ceiling_wall_top = middle + (VISPLANE_DIST * (person_height - in_info.ceiling_elev) / cast_dist) as isize;
+                let ceiling_wall_top = middle + (visplane_dist * (person_height - in_info.ceiling_elev) / cast_dist) as isize;
                 let ceiling_wall_bottom = ceiling_wall_top + ceiling_wall_seg_height_px;
 
                 let draw_ceiling_wall_top = if ceiling_wall_top < render_top { render_top } else { ceiling_wall_top };
                 let draw_ceiling_wall_bottom = if ceiling_wall_bottom > render_bottom { render_bottom } else { ceiling_wall_bottom };
 
-                ctx.draw_wall(x, draw_ceiling_wall_top, draw_ceiling_wall_bottom, 0, along, cast_dist);
-                ctx.draw_flat(x, draw_floor_wall_bottom, render_bottom, person_height - in_info.floor_elev, self.pos, hit_pos, cos_offset, -looking_offset);
+                if let Some(i) = out_info {
+                    let tex = wall_tex_coords(&wall.upper, along, in_info.ceiling_elev, i.ceiling_elev, &proj);
+                    ctx.draw_wall(ColumnSpan { x: x, top: draw_ceiling_wall_top, bottom: draw_ceiling_wall_bottom }, textures.get(wall.upper.texture), tex, shading);
+                }
+
+                let floor_ray = FlatRay { elevation: person_height - in_info.floor_elev, pos: cam.pos, hit_pos: hit_pos, cos_angle: cos_offset, look: -looking_offset };
+                ctx.draw_flat(ColumnSpan { x: x, top: draw_floor_wall_bottom, bottom: render_bottom }, textures.get(in_info.floor_tex), &in_info, floor_ray);
 
                 if in_info.ceiling_elev > 22.0 {
                     ctx.draw_sky(sky, x, render_top, draw_ceiling_wall_top);
                 } else {
-                    ctx.draw_flat(x, render_top, draw_ceiling_wall_top, person_height - in_info.ceiling_elev, self.pos, hit_pos, cos_offset, -looking_offset);
+                    let ceiling_ray = FlatRay { elevation: person_height - in_info.ceiling_elev, ..floor_ray };
+                    ctx.draw_flat(ColumnSpan { x: x, top: render_top, bottom: draw_ceiling_wall_top }, textures.get(in_info.ceiling_tex), &in_info, ceiling_ray);
                 }
 
                 if ceiling_wall_bottom > render_top { render_top = ceiling_wall_bottom; }
                 if floor_wall_top < render_bottom { render_bottom = floor_wall_top; }
+
+                clip.push(x, cast_dist, render_top, render_bottom);
             }
         }
 
+        self.render_sprites(cam, textures, ctx, clip, h as isize / 2 + looking_offset);
+
         ctx.draw_seg(LineSeg::new(0.0, -3.0, 0.0, 4.0), 0xff, 0xff, 0xff);
         ctx.draw_seg(LineSeg::new(-3.0, 0.0, 4.0, 0.0), 0xff, 0xff, 0xff);
-        ctx.put_px(160, 120, 0x00, 0x00, 0x00);
+        let (center_x, center_y) = (w / 2, h / 2);
+        if ctx.columns.contains(&center_x) {
+            ctx.put_px(center_x, center_y, 0x00, 0x00, 0x00);
+        }
+    }
+
+    // Draws sprites back to front after the ray caster has filled the frame.
+    fn render_sprites(&self, cam: &Camera, textures: &TextureRegistry, ctx: &mut RenderContext, clip: &DepthClip, horizon: isize) {
+        let forward = Vec2::new(cam.face_angle.sin(), -cam.face_angle.cos());
+        let right = Vec2::new(cam.face_angle.cos(), cam.face_angle.sin());
+
+        let mut visible: Vec<(f32, f32, &Sprite)> = self.sprites.iter()
+            .map(|sprite| {
+                let offset = sprite.pos - cam.pos;
+                (offset.dot(forward), offset.dot(right), sprite)
+            })
+            .filter(|&(depth, _, _)| depth > SPRITE_NEAR_CLIP)
+            .collect();
+
+        visible.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+        for (depth, lateral, sprite) in visible {
+            let px_per_unit = ctx.visplane_dist / depth;
+            let texture = textures.get(sprite.texture);
+            let SectorIndex(sec_index) = sprite.sector;
+            let info = self.world.get_sectors()[sec_index].info;
+
+            let center = ctx.width as f32 / 2.0 + lateral * px_per_unit;
+            let left = center - sprite.width * px_per_unit / 2.0;
+            let right = center + sprite.width * px_per_unit / 2.0;
+            let bottom = horizon as f32 + (cam.eye_elev - info.floor_elev) * px_per_unit;
+            let top = bottom - sprite.height * px_per_unit;
+
+            let texels_per_px = texture.width as f32 / (right - left);
+            let v_step = texture.height as f32 / (bottom - top);
+
+            let shading = Shading { info: &info, dist: depth };
+
+            let first = (left.max(0.0).round() as usize).max(ctx.columns.start);
+            let last = (right.min(ctx.width as f32).round() as usize).min(ctx.columns.end);
+
+            for x in first..last {
+                let (clip_top, clip_bottom) = clip.window(x, depth);
+                let draw_top = (top.round() as isize).max(clip_top);
+                let draw_bottom = (bottom.round() as isize).min(clip_bottom);
+                let u = ((x as f32 + 0.5 - left) * texels_per_px).min(texture.width as f32 - 1.0);
+
+                let tex = TexSpan { u: u, v_top: (0.5 - top) * v_step, v_step: v_step };
+                ctx.draw_sprite(ColumnSpan { x: x, top: draw_top, bottom: draw_bottom }, texture, tex, shading);
+            }
+        }
     }
 }
 
@@ -134,17 +569,18 @@ pub fn get_px(tex: &[u8], x: usize, y: usize, w: usize) -> (u8,u8,u8) {
 
 impl<'a> RenderContext<'a> {
     pub fn put_px(&mut self, x: usize, y: usize, r: u8, g: u8, b: u8) {
-        self.pixels[4*(self.width as usize*y+x) + 0] = b;
-        self.pixels[4*(self.width as usize*y+x) + 1] = g;
-        self.pixels[4*(self.width as usize*y+x) + 2] = r;
-        self.pixels[4*(self.width as usize*y+x) + 3] = 0xff;
+        let i = 4*(self.columns.len()*y + x - self.columns.start);
+        self.pixels[i + 0] = b;
+        self.pixels[i + 1] = g;
+        self.pixels[i + 2] = r;
+        self.pixels[i + 3] = 0xff;
     }
 
     pub fn clear(&mut self, shade: u8) {
         use std::{mem, ptr};
         unsafe {
             let ptr: *mut u8 = mem::transmute(&self.pixels[0]);
-            ptr::write_bytes(ptr, shade, (4*self.width*self.height) as usize);
+            ptr::write_bytes(ptr, shade, self.pixels.len());
         }
     }
 
@@ -153,10 +589,12 @@ impl<'a> RenderContext<'a> {
 
         for t in 0..(len as usize) {
             let pt = seg.at(t as f32 / len);
-            let ux = (pt.x + (self.width /2) as f32) as usize;
-            let uy = (pt.y + (self.height/2) as f32) as usize;
+            let x = pt.x + (self.width /2) as f32;
+            let y = pt.y + (self.height/2) as f32;
+            if x < 0.0 || y < 0.0 { continue; }
+            let (ux, uy) = (x as usize, y as usize);
 
-            if ux < self.width as usize && uy < self.height as usize {
+            if self.columns.contains(&ux) && uy < self.height as usize {
                 self.put_px(ux, uy, r, g, b);
             }
         }
@@ -172,31 +610,68 @@ impl<'a> RenderContext<'a> {
         }
     }
 
-    pub fn draw_wall(&mut self, x: usize, top: isize, bottom: isize, y_offset: isize, along:f32, cast_dist: f32) {
-        for y in self.column_range(top, bottom) {
-            let yy = ((bottom + y_offset) as usize - y) as f32 * cast_dist / 5000.0;
-            let tex_lookup = (along * 25.0) as u8 ^ (512.0*yy) as u8;
-            let color = ((tex_lookup as f32) * brightness_from_dist(cast_dist)) as u8;
-            self.put_px(x, y, color / 2, color / 2, color);
+    pub fn draw_wall(&mut self, span: ColumnSpan, texture: &Texture, tex: TexSpan, shading: Shading) {
+        for y in self.column_range(span.top, span.bottom) {
+            let (r, g, b) = shade(shading.info, shading.dist, texture.sample(tex.u, tex.v_top + y as f32 * tex.v_step));
+            self.put_px(span.x, y, r, g, b);
         }
     }
 
-    pub fn draw_flat(&mut self, x: usize, top: isize, bottom: isize, elevation: f32, pos: Vec2, hit_pos: Vec2, cos_angle: f32, look: isize) {
-        for y in self.column_range(top, bottom) {
-            let dist_floor = VISPLANE_DIST * elevation / ((y as isize + look) as f32 - self.height as f32 / 2.0);
-            let floor_pos = pos + (hit_pos - pos).normalize() * dist_floor / cos_angle;
-            let tex_lookup = (floor_pos.x * 10.0) as u8 ^ (floor_pos.y * 10.0) as u8;
-            let color = (tex_lookup as f32 * brightness_from_dist(dist_floor)) as u8;
-            self.put_px(x, y, 0x00, color, 0x00);
+    // Skips transparent texels, V is sampled at the middle of each row.
+    pub fn draw_sprite(&mut self, span: ColumnSpan, texture: &Texture, tex: TexSpan, shading: Shading) {
+        for y in self.column_range(span.top, span.bottom) {
+            let v = (tex.v_top + y as f32 * tex.v_step).min(texture.height as f32 - 1.0);
+            let (r, g, b, a) = texture.sample_rgba(tex.u, v);
+            if a < 0x80 { continue; }
+            let (r, g, b) = shade(shading.info, shading.dist, (r, g, b));
+            self.put_px(span.x, y, r, g, b);
+        }
+    }
+
+    pub fn draw_flat(&mut self, span: ColumnSpan, texture: &Texture, info: &SectorInfo, ray: FlatRay) {
+        for y in self.column_range(span.top, span.bottom) {
+            let dist_floor = self.visplane_dist * ray.elevation / ((y as isize + ray.look) as f32 - self.height as f32 / 2.0);
+            let floor_pos = ray.pos + (ray.hit_pos - ray.pos).normalize() * dist_floor / ray.cos_angle;
+            let (r, g, b) = shade(info, dist_floor, texture.sample(floor_pos.x * TEXELS_PER_UNIT, floor_pos.y * TEXELS_PER_UNIT));
+            self.put_px(span.x, y, r, g, b);
+        }
+    }
+
+    // Each line of `text` is placed with its top at `y`, and its left edge, centre or right edge
+    // at `x` according to `align`.
+    pub fn draw_text(&mut self, font: &Font, text: &str, x: isize, y: isize, align: Align, (r, g, b): (u8,u8,u8)) {
+        for (line_index, line) in text.lines().enumerate() {
+            let line_width = font.text_width(line) as isize;
+            let left = match align {
+                Align::Left   => x,
+                Align::Center => x - line_width / 2,
+                Align::Right  => x - line_width,
+            };
+            let top = y + (line_index * font.glyph_height) as isize;
+
+            for (char_index, c) in line.chars().enumerate() {
+                let glyph_left = left + (char_index * font.glyph_width) as isize;
+
+                for glyph_y in 0..font.glyph_height {
+                    for glyph_x in 0..font.glyph_width {
+                        let (px, py) = (glyph_left + glyph_x as isize, top + glyph_y as isize);
+                        if px < 0 || py < 0 || py >= self.height || !self.columns.contains(&(px as usize)) { continue; }
+
+                        if font.is_set(c, glyph_x, glyph_y) {
+                            self.put_px(px as usize, py as usize, r, g, b);
+                        }
+                    }
+                }
+            }
         }
     }
 
-    pub fn draw_sky(&mut self, sky: &mut Surface, x: usize, top: isize, bottom: isize) {
+    pub fn draw_sky(&mut self, sky: &Sky, x: usize, top: isize, bottom: isize) {
+        let sky_x = x * sky.width / self.width as usize;
         for y in self.column_range(top, bottom) {
-            sky.with_lock(|buffer| {
-                let (r,g,b) = get_px(buffer, x, y, self.width as usize);
-                self.put_px(x, y, r, g, b);
-            });
+            let sky_y = y * sky.height / self.height as usize;
+            let (r,g,b) = get_px(sky.pixels, sky_x, sky_y, sky.width);
+            self.put_px(x, y, r, g, b);
         }
     }
 }
@@ -205,3 +680,44 @@ fn brightness_from_dist(dist: f32) -> f32 {
     (20.0 / dist).min(1.0).max(0.0)
 }
 
+// Maps a wall section spanning `top_elev` to `bottom_elev` to texture space. V runs down
+// from whichever edge the texture is pegged to, measured in world units so that the
+// texture stays fixed to the wall rather than stretching with the section's height.
+fn wall_tex_coords(wall_tex: &WallTexture, along: f32, top_elev: f32, bottom_elev: f32, proj: &Projection) -> TexSpan {
+    let texels_per_unit = TEXELS_PER_UNIT / wall_tex.scale;
+    let anchor_elev = match wall_tex.peg {
+        Peg::Top    => top_elev,
+        Peg::Bottom => bottom_elev,
+    };
+    let units_per_px = proj.cast_dist / proj.visplane_dist;
+
+    TexSpan {
+        u: along * texels_per_unit + wall_tex.offset_x,
+        v_top: (anchor_elev - proj.eye_height - proj.horizon as f32 * units_per_px) * texels_per_unit + wall_tex.offset_y,
+        v_step: units_per_px * texels_per_unit,
+    }
+}
+
+// Applies the sector's light level, then fades with distance either towards black or,
+// in a fogged sector, towards the fog colour.
+fn shade(info: &SectorInfo, dist: f32, (r, g, b): (u8,u8,u8)) -> (u8,u8,u8) {
+    let (brightness, fog_color, fog_amount) = match info.fog {
+        Some(fog) => (info.light, fog.color, 1.0 - (-fog.density * dist).exp()),
+        None      => (info.light * brightness_from_dist(dist), (0, 0, 0), 0.0),
+    };
+
+    (
+        mix(r, brightness, fog_color.0, fog_amount),
+        mix(g, brightness, fog_color.1, fog_amount),
+        mix(b, brightness, fog_color.2, fog_amount),
+    )
+}
+
+fn mix(channel: u8, brightness: f32, fog: u8, fog_amount: f32) -> u8 {
+    (channel as f32 * brightness * (1.0 - fog_amount) + fog as f32 * fog_amount) as u8
+}
+
+fn blend(under: u8, over: u8, amount: f32) -> u8 {
+    (under as f32 * (1.0 - amount) + over as f32 * amount) as u8
+}
+
@@ -0,0 +1,267 @@
+// Golden-image regression tests for the software renderer.
+//
+// Each test renders a fixed camera pose in `world::temp()`, or the map seen from one, and
+// compares it against `tests/golden/<name>.png`. On a mismatch the actual frame and a diff image are written
+// to `target/golden/`. Run with `RUSTYCAST_BLESS=1` to regenerate the reference images.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sdl2::surface::Surface;
+use sdl2_image::LoadSurface;
+
+use automap::OverlayMode;
+use builder::WorldBuilder;
+use font::Font;
+use game::Game;
+use headless;
+use hud::Anchor;
+use math::Vec2;
+use settings::DEFAULT_FOV;
+use test_support::{info, rect};
+use texture::{Texture, TextureRegistry};
+use world::{self, SectorIndex};
+
+
+const W: usize = 320;
+const H: usize = 240;
+
+const CHANNEL_TOLERANCE: u8 = 2;
+const MAX_BAD_PIXELS: usize = W * H / 1000;
+
+
+fn manifest_path(rel: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(rel)
+}
+
+fn check_golden(name: &str, pos: Vec2, face_angle: f32, look_angle: f32) {
+    let world = world::temp();
+    let mut sky: Surface = LoadSurface::from_file(&manifest_path("res/sky.png")).unwrap();
+    let textures = TextureRegistry::load(&manifest_path("res/textures"), world.get_texture_names()).unwrap();
+    let actual = headless::render_frame(&world, &mut sky, &textures, pos, face_angle, look_angle, DEFAULT_FOV, 1, W, H).unwrap();
+
+    assert_golden(name, actual);
+}
+
+// A game standing at the origin of `world::temp()`, facing north.
+fn temp_game() -> Game {
+    Game::new(world::temp(), SectorIndex(0), Vec2::new(0.0, 0.0), 0.0)
+}
+
+fn render(game: &Game) -> Vec<u8> {
+    let mut sky: Surface = LoadSurface::from_file(&manifest_path("res/sky.png")).unwrap();
+    let textures = TextureRegistry::load(&manifest_path("res/textures"), game.world.get_texture_names()).unwrap();
+    let mut pixels = vec![0u8; 4 * W * H];
+    game.render(&mut sky, &textures, &mut pixels, W, H, 1.0);
+    pixels
+}
+
+fn assert_golden(name: &str, mut actual: Vec<u8>) {
+    let golden_path = manifest_path("tests/golden").join(format!("{}.png", name));
+
+    if env::var("RUSTYCAST_BLESS").is_ok() {
+        fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        headless::save_png(&mut actual, W, H, &golden_path).unwrap();
+        return;
+    }
+
+    let expected = Texture::load(&golden_path)
+        .unwrap_or_else(|err| panic!("cannot load {}: {} (run with RUSTYCAST_BLESS=1 to create it)", golden_path.display(), err));
+    assert_eq!((expected.width, expected.height), (W, H), "{} has the wrong dimensions", golden_path.display());
+    let expected = expected.pixels;
+
+    let mut diff = vec![0u8; 4 * W * H];
+    let mut bad_pixels = 0;
+
+    for i in 0..W * H {
+        let px = 4 * i;
+        let delta = (0..3)
+            .map(|c| (actual[px + c] as i16 - expected[px + c] as i16).abs() as u8)
+            .max().unwrap();
+
+        if delta > CHANNEL_TOLERANCE {
+            bad_pixels += 1;
+            diff[px .. px + 4].copy_from_slice(&[0x00, 0x00, 0xff, 0xff]);
+        } else {
+            let grey = actual[px .. px + 3].iter().map(|&c| c as u32).sum::<u32>() / 12;
+            diff[px .. px + 4].copy_from_slice(&[grey as u8, grey as u8, grey as u8, 0xff]);
+        }
+    }
+
+    if bad_pixels > MAX_BAD_PIXELS {
+        let out_dir = manifest_path("target/golden");
+        fs::create_dir_all(&out_dir).unwrap();
+        let actual_path = out_dir.join(format!("{}.actual.png", name));
+        let diff_path = out_dir.join(format!("{}.diff.png", name));
+        headless::save_png(&mut actual, W, H, &actual_path).unwrap();
+        headless::save_png(&mut diff, W, H, &diff_path).unwrap();
+
+        panic!("{}: {} pixels differ from {} (allowed {}), see {} and {}",
+            name, bad_pixels, golden_path.display(), MAX_BAD_PIXELS, actual_path.display(), diff_path.display());
+    }
+}
+
+
+// The red of solid walls and the gold of steps. Portals which don't change height are drawn in
+// grey, as checked by `map_colors_walls_by_kind`.
+#[test]
+fn full_map() {
+    let mut game = temp_game();
+    game.show_map = true;
+    assert_golden("full_map", render(&game));
+}
+
+// Only walls the view's rays reached are drawn after the first frame.
+#[test]
+fn seen_only_map() {
+    let mut game = temp_game();
+    game.automap.seen_only = true;
+    render(&game);
+    game.show_map = true;
+    assert_golden("seen_only_map", render(&game));
+}
+
+// The corner overlay darkens the view behind it, the full one only blends in the lines.
+#[test]
+fn corner_overlay() {
+    let mut game = temp_game();
+    game.automap.overlay.mode = OverlayMode::Corner;
+    assert_golden("corner_overlay", render(&game));
+}
+
+#[test]
+fn full_overlay() {
+    let mut game = temp_game();
+    game.automap.overlay.mode = OverlayMode::Full;
+    assert_golden("full_overlay", render(&game));
+}
+
+// A widget in every corner, stacked on the status widgets where they share one, and a
+// message below the top left widgets.
+#[test]
+fn hud() {
+    let mut game = temp_game();
+    game.hud.font = Some(Font::load(&manifest_path("res/font.png")).unwrap());
+    game.hud.add_status_widgets();
+    game.hud.add_widget(Anchor::TopLeft, (0x60, 0xff, 0x60), |_| "TOP LEFT".to_string());
+    game.hud.add_widget(Anchor::TopRight, (0xff, 0x60, 0x60), |_| "TOP\nRIGHT".to_string());
+    game.hud.add_widget(Anchor::BottomRight, (0x60, 0x60, 0xff), |_| "BOTTOM RIGHT".to_string());
+    game.hud.show_message("Message");
+    assert_golden("hud", render(&game));
+}
+
+// Screenshots are taken with render_view, which leaves out the console.
+#[test]
+fn view_leaves_out_the_console() {
+    let mut game = temp_game();
+    game.hud.font = Some(Font::load(&manifest_path("res/font.png")).unwrap());
+    let closed = render(&game);
+
+    game.console.toggle();
+    game.console.step(1.0);
+    let mut sky: Surface = LoadSurface::from_file(&manifest_path("res/sky.png")).unwrap();
+    let textures = TextureRegistry::load(&manifest_path("res/textures"), game.world.get_texture_names()).unwrap();
+    let mut view = vec![0u8; 4 * W * H];
+    game.render_view(&mut sky, &textures, &mut view, W, H, 1.0);
+
+    assert!(render(&game) != closed);
+    assert!(view == closed);
+}
+
+#[test]
+fn map_colors_walls_by_kind() {
+    // Three rooms in a row, the player in the middle one and the east one a step up.
+    let mut builder = WorldBuilder::new();
+    let texture = builder.add_texture("brick");
+    for &(x0, floor_elev) in &[(-30.0, 0.0), (-10.0, 0.0), (10.0, 2.0)] {
+        builder.add_sector(info(floor_elev, 20.0), texture, &rect(x0, -10.0, x0 + 20.0, 10.0));
+    }
+    let mut game = Game::new(builder.build(), SectorIndex(1), Vec2::new(0.0, 0.0), 0.0);
+    game.show_map = true;
+    let pixels = render(&game);
+
+    // The first drawn colour around a pixel, as lines may be off by one after rounding.
+    let color = |x: usize, y: usize| (y - 1 ..= y + 1)
+        .flat_map(|y| (x - 1 ..= x + 1).map(move |x| 4 * (W * y + x)))
+        .map(|px| (pixels[px + 2], pixels[px + 1], pixels[px]))
+        .find(|&rgb| rgb != (0, 0, 0));
+
+    // At the default scale of 2 pixels per unit, around the player in the middle of the screen.
+    assert_eq!(color(W / 2, H / 2 - 20), Some((0xff, 0x00, 0x00)));
+    assert_eq!(color(W / 2 - 20, H / 2 + 10), Some((0x50, 0x50, 0x50)));
+    assert_eq!(color(W / 2 + 20, H / 2 + 10), Some((0xff, 0xd0, 0x40)));
+}
+
+#[test]
+fn origin_facing_north() {
+    check_golden("origin_facing_north", Vec2::new(0.0, 0.0), 0.0, 0.0);
+}
+
+#[test]
+fn raised_sector_step() {
+    check_golden("raised_sector_step", Vec2::new(0.0, 0.0), -0.85, 0.0);
+}
+
+#[test]
+fn sunken_sector_pit() {
+    check_golden("sunken_sector_pit", Vec2::new(0.0, 0.0), 2.36, 0.0);
+}
+
+#[test]
+fn looking_up_clips_ceiling() {
+    check_golden("looking_up_clips_ceiling", Vec2::new(0.0, 0.0), -0.85, -100.0);
+}
+
+#[test]
+fn looking_down_clips_floor() {
+    check_golden("looking_down_clips_floor", Vec2::new(0.0, 0.0), -0.85, 100.0);
+}
+
+#[test]
+fn inside_raised_sector() {
+    check_golden("inside_raised_sector", Vec2::new(-18.0, -18.0), 0.8, 0.0);
+}
+
+#[test]
+fn inside_sunken_sector() {
+    check_golden("inside_sunken_sector", Vec2::new(17.0, 17.0), -2.36, 0.0);
+}
+
+// Bands which don't divide the width evenly, and a band narrower than a sprite, must
+// still produce exactly the serial frame.
+#[test]
+fn threaded_render_matches_serial() {
+    let world = world::temp();
+    let mut sky: Surface = LoadSurface::from_file(&manifest_path("res/sky.png")).unwrap();
+    let textures = TextureRegistry::load(&manifest_path("res/textures"), world.get_texture_names()).unwrap();
+
+    for &(pos, face_angle) in &[(Vec2::new(0.0, 0.0), 0.0), (Vec2::new(0.0, 0.0), -0.85), (Vec2::new(-18.0, -18.0), 0.8)] {
+        let serial = headless::render_frame(&world, &mut sky, &textures, pos, face_angle, 0.0, DEFAULT_FOV, 1, W, H).unwrap();
+
+        for &threads in &[2, 3, 7, 64] {
+            let threaded = headless::render_frame(&world, &mut sky, &textures, pos, face_angle, 0.0, DEFAULT_FOV, threads, W, H).unwrap();
+            assert!(threaded == serial, "{} threads differ from the serial render at angle {}", threads, face_angle);
+        }
+    }
+}
+
+#[test]
+fn reused_render_pool_matches_serial() {
+    // One game renders every frame, so the workers and band buffers carry over from the
+    // previous frame, and are rebuilt for the last one.
+    let world = world::temp();
+    let mut sky: Surface = LoadSurface::from_file(&manifest_path("res/sky.png")).unwrap();
+    let textures = TextureRegistry::load(&manifest_path("res/textures"), world.get_texture_names()).unwrap();
+    let mut game = Game::new(world.clone(), SectorIndex(0), Vec2::new(0.0, 0.0), 0.0);
+    let mut pixels = vec![0u8; 4 * W * H];
+
+    for &(face_angle, threads) in &[(0.0, 3), (-0.85, 3), (2.5, 3), (1.0, 5)] {
+        let serial = headless::render_frame(&world, &mut sky, &textures, Vec2::new(0.0, 0.0), face_angle, 0.0, DEFAULT_FOV, 1, W, H).unwrap();
+
+        game.face_angle = face_angle;
+        game.render_threads = threads;
+        game.render(&mut sky, &textures, &mut pixels, W, H, 1.0);
+        assert!(pixels == serial, "{} threads differ from the serial render at angle {}", threads, face_angle);
+    }
+}
@@ -0,0 +1,52 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+
+pub const RES_DIR_VAR: &str = "RUSTYCAST_RES";
+
+
+// Finds assets by searching its directories in order.
+pub struct Resources {
+    _dirs: Vec<PathBuf>,
+}
+
+
+impl Resources {
+    // Searches the directory given on the command line, the one named by $RUSTYCAST_RES, a
+    // `res` folder next to the executable and the crate's own `res`.
+    pub fn new(cli_dir: Option<&Path>) -> Resources {
+        let mut dirs = vec![];
+
+        if let Some(dir) = cli_dir {
+            dirs.push(dir.to_path_buf());
+        }
+        if let Some(dir) = env::var_os(RES_DIR_VAR) {
+            dirs.push(PathBuf::from(dir));
+        }
+        if let Some(dir) = env::current_exe().ok().as_ref().and_then(|exe| exe.parent()) {
+            dirs.push(dir.join("res"));
+        }
+        dirs.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("res"));
+
+        Resources::from_dirs(&dirs)
+    }
+
+    pub fn from_dirs(dirs: &[PathBuf]) -> Resources {
+        Resources {
+            _dirs: dirs.to_vec(),
+        }
+    }
+
+    // `rel` is relative to the resource directory, eg. "textures/brick.png".
+    pub fn find(&self, rel: &str) -> Result<PathBuf, String> {
+        let candidates: Vec<PathBuf> = self._dirs.iter().map(|dir| dir.join(rel)).collect();
+
+        match candidates.iter().find(|path| path.exists()) {
+            Some(path) => Ok(path.clone()),
+            None => {
+                let searched: Vec<String> = candidates.iter().map(|path| format!("  {}", path.display())).collect();
+                Err(format!("cannot find {}, searched:\n{}", rel, searched.join("\n")))
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Tests for the order `Resources` searches its directories in, using directories under a
+// fresh temporary folder which is removed again however the test ends.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use resources::Resources;
+
+
+const PROBE: &str = "maps/temp.map";
+
+
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("rustycast-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path: path }
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+fn place(dir: &Path) {
+    fs::create_dir_all(dir.join(PROBE).parent().unwrap()).unwrap();
+    fs::write(dir.join(PROBE), "").unwrap();
+}
+
+// The lines of the error listing the paths searched for `rel`.
+fn searched(resources: &Resources, rel: &str) -> Vec<String> {
+    let err = resources.find(rel).unwrap_err();
+    err.lines().skip(1).map(|line| line.trim().to_string()).collect()
+}
+
+
+#[test]
+fn first_directory_holding_the_file_wins() {
+    let temp = TempDir::new("search-order");
+    let dirs: Vec<PathBuf> = ["first", "second", "third"].iter().map(|name| temp.path.join(name)).collect();
+    let resources = Resources::from_dirs(&dirs);
+
+    for dir in dirs.iter() {
+        place(dir);
+    }
+
+    // Removing the file from each directory in turn uncovers the next one along.
+    for dir in dirs.iter() {
+        assert_eq!(resources.find(PROBE).unwrap(), dir.join(PROBE));
+        fs::remove_file(dir.join(PROBE)).unwrap();
+    }
+    assert!(resources.find(PROBE).is_err());
+}
+
+#[test]
+fn missing_file_lists_every_directory_in_order() {
+    let temp = TempDir::new("missing");
+    let dirs = vec![temp.path.join("first"), temp.path.join("second")];
+    let resources = Resources::from_dirs(&dirs);
+
+    let expected: Vec<String> = dirs.iter().map(|dir| dir.join(PROBE).display().to_string()).collect();
+    assert_eq!(searched(&resources, PROBE), expected);
+}
+
+#[test]
+fn command_line_directory_is_searched_first() {
+    let temp = TempDir::new("cli");
+    let resources = Resources::new(Some(&temp.path));
+
+    let searched = searched(&resources, "missing.png");
+    assert_eq!(searched[0], temp.path.join("missing.png").display().to_string());
+    assert_eq!(searched.last().unwrap(), &Path::new(env!("CARGO_MANIFEST_DIR")).join("res/missing.png").display().to_string());
+}
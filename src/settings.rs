@@ -0,0 +1,264 @@
+use std::fs;
+use std::path::Path;
+
+use sdl2::keyboard::Keycode;
+use toml::{Table, Value};
+
+use input::{self, Key};
+use automap::OverlayMode;
+
+
+// Horizontal field of view in degrees, the screen is 300 pixels from the eye at 320
+// pixels wide.
+pub const DEFAULT_FOV: f32 = 56.144974;
+
+pub const DEFAULT_CONFIG_PATH: &str = "rustycast.toml";
+
+
+// `width` and `height` are the size of the rendered frame, the window is `scale` times
+// larger.
+#[derive(Clone,Copy)]
+pub struct VideoSettings {
+    pub width: usize,
+    pub height: usize,
+    pub scale: u32,
+    pub fov: f32,
+}
+
+// `sensitivity` scales both turning and looking, `look_limit` is how many pixels the view
+// can be shifted up or down.
+#[derive(Clone,Copy)]
+pub struct MouseSettings {
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    pub look_limit: f32,
+}
+
+// The map drawn over the 3D view, `scale` is in pixels per world unit.
+#[derive(Clone,Copy)]
+pub struct OverlaySettings {
+    pub mode: OverlayMode,
+    pub opacity: f32,
+    pub scale: f32,
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    pub video: VideoSettings,
+    pub mouse: MouseSettings,
+    pub overlay: OverlaySettings,
+    pub bindings: Vec<(Keycode, Key)>,
+}
+
+
+impl Settings {
+    pub fn default() -> Settings {
+        Settings {
+            video: VideoSettings::default(),
+            mouse: MouseSettings::default(),
+            overlay: OverlaySettings::default(),
+            bindings: input::default_bindings(),
+        }
+    }
+
+    // Anything not given in the file is left at its default.
+    pub fn load(path: &Path) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let table = text.parse::<Table>().map_err(|e| e.to_string())?;
+
+        for (section, value) in table.iter() {
+            match (section.as_str(), value) {
+                ("video", &Value::Table(ref video)) => settings.video.apply(video)?,
+                ("mouse", &Value::Table(ref mouse)) => settings.mouse.apply(mouse)?,
+                ("map",   &Value::Table(ref map))   => settings.overlay.apply(map)?,
+                ("keys",  &Value::Table(ref keys))  => apply_bindings(&mut settings.bindings, keys)?,
+                _ => return Err(format!("unknown section `{}`", section)),
+            }
+        }
+
+        settings.video.check()?;
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut video = Table::new();
+        video.insert("width".to_string(), Value::Integer(self.video.width as i64));
+        video.insert("height".to_string(), Value::Integer(self.video.height as i64));
+        video.insert("scale".to_string(), Value::Integer(self.video.scale as i64));
+        video.insert("fov".to_string(), float_value(self.video.fov));
+
+        let mut mouse = Table::new();
+        mouse.insert("sensitivity".to_string(), float_value(self.mouse.sensitivity));
+        mouse.insert("invert_y".to_string(), Value::Boolean(self.mouse.invert_y));
+        mouse.insert("look_limit".to_string(), float_value(self.mouse.look_limit));
+
+        let mut map = Table::new();
+        map.insert("overlay".to_string(), Value::String(self.overlay.mode.name().to_string()));
+        map.insert("overlay_opacity".to_string(), float_value(self.overlay.opacity));
+        map.insert("overlay_scale".to_string(), float_value(self.overlay.scale));
+
+        let mut keys = Table::new();
+        for &key in input::ALL_KEYS.iter() {
+            let names = self.bindings.iter()
+                .filter(|&&(_, k)| k == key)
+                .map(|&(keycode, _)| Value::String(keycode.name()))
+                .collect();
+            keys.insert(key.name().to_string(), Value::Array(names));
+        }
+
+        let mut table = Table::new();
+        table.insert("video".to_string(), Value::Table(video));
+        table.insert("mouse".to_string(), Value::Table(mouse));
+        table.insert("map".to_string(), Value::Table(map));
+        table.insert("keys".to_string(), Value::Table(keys));
+
+        fs::write(path, table.to_string()).map_err(|e| e.to_string())
+    }
+}
+
+impl VideoSettings {
+    pub fn default() -> VideoSettings {
+        VideoSettings {
+            width: 320,
+            height: 240,
+            scale: 3,
+            fov: DEFAULT_FOV,
+        }
+    }
+
+    fn apply(&mut self, table: &Table) -> Result<(), String> {
+        for (key, value) in table.iter() {
+            match key.as_str() {
+                "width"  => self.width = parse_count(key, value)? as usize,
+                "height" => self.height = parse_count(key, value)? as usize,
+                "scale"  => self.scale = parse_count(key, value)?,
+                "fov"    => self.fov = parse_float(key, value)?,
+                _ => return Err(format!("unknown video setting `{}`", key)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!("invalid resolution {}x{}", self.width, self.height));
+        }
+        if self.scale == 0 {
+            return Err("scale must be at least 1".to_string());
+        }
+        // SDL takes the window's size in pixels as u32s.
+        let fits = |size: usize| (size as u64).checked_mul(self.scale as u64).map_or(false, |pixels| pixels <= u32::MAX as u64);
+        if !fits(self.width) || !fits(self.height) {
+            return Err(format!("window of {}x{} at scale {} is too large", self.width, self.height, self.scale));
+        }
+        if self.fov.is_nan() || self.fov <= 0.0 || self.fov >= 180.0 {
+            return Err(format!("fov must be between 0 and 180 degrees, got {}", self.fov));
+        }
+        Ok(())
+    }
+}
+
+impl MouseSettings {
+    pub fn default() -> MouseSettings {
+        MouseSettings {
+            sensitivity: 1.0,
+            invert_y: false,
+            look_limit: 120.0,
+        }
+    }
+
+    fn apply(&mut self, table: &Table) -> Result<(), String> {
+        for (key, value) in table.iter() {
+            match key.as_str() {
+                "sensitivity" => self.sensitivity = parse_float(key, value)?,
+                "invert_y"    => self.invert_y = parse_bool(key, value)?,
+                "look_limit"  => self.look_limit = parse_float(key, value)?.max(0.0),
+                _ => return Err(format!("unknown mouse setting `{}`", key)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OverlaySettings {
+    pub fn default() -> OverlaySettings {
+        OverlaySettings {
+            mode: OverlayMode::Off,
+            opacity: 0.6,
+            scale: 1.0,
+        }
+    }
+
+    fn apply(&mut self, table: &Table) -> Result<(), String> {
+        for (key, value) in table.iter() {
+            match key.as_str() {
+                "overlay" => {
+                    let name = value.as_str().ok_or(format!("`{}` expects a string", key))?;
+                    self.mode = OverlayMode::from_name(name).ok_or(format!("unknown overlay `{}`, expected off, corner or full", name))?;
+                }
+                "overlay_opacity" => self.opacity = parse_float(key, value)?.min(1.0).max(0.0),
+                "overlay_scale" => {
+                    self.scale = parse_float(key, value)?;
+                    if self.scale.is_nan() || self.scale <= 0.0 {
+                        return Err("`overlay_scale` must be positive".to_string());
+                    }
+                }
+                _ => return Err(format!("unknown map setting `{}`", key)),
+            }
+        }
+        Ok(())
+    }
+}
+
+
+// Each entry replaces every binding of its key, and takes either one SDL key name or a
+// list of them.
+fn apply_bindings(bindings: &mut Vec<(Keycode, Key)>, table: &Table) -> Result<(), String> {
+    for (name, value) in table.iter() {
+        let key = Key::from_name(name).ok_or(format!("unknown key binding `{}`", name))?;
+
+        let keycode_names = match *value {
+            Value::String(ref s) => vec![s.as_str()],
+            Value::Array(ref values) => values.iter()
+                .map(|v| v.as_str().ok_or(format!("`{}` expects key names", name)))
+                .collect::<Result<Vec<&str>, String>>()?,
+            _ => return Err(format!("`{}` expects a key name or a list of them", name)),
+        };
+
+        bindings.retain(|&(_, k)| k != key);
+
+        for keycode_name in keycode_names {
+            let keycode = Keycode::from_name(keycode_name).ok_or(format!("unknown key `{}` for `{}`", keycode_name, name))?;
+            bindings.push((keycode, key));
+        }
+    }
+    Ok(())
+}
+
+fn parse_count(key: &str, value: &Value) -> Result<u32, String> {
+    match *value {
+        Value::Integer(n) if n > 0 && n <= u32::MAX as i64 => Ok(n as u32),
+        Value::Integer(n) if n > 0 => Err(format!("`{}` is too large, got {}", key, n)),
+        _ => Err(format!("`{}` expects a positive integer", key)),
+    }
+}
+
+fn parse_float(key: &str, value: &Value) -> Result<f32, String> {
+    match *value {
+        Value::Float(f) => Ok(f as f32),
+        Value::Integer(n) => Ok(n as f32),
+        _ => Err(format!("`{}` expects a number", key)),
+    }
+}
+
+fn parse_bool(key: &str, value: &Value) -> Result<bool, String> {
+    value.as_bool().ok_or(format!("`{}` expects true or false", key))
+}
+
+// Goes through the shortest decimal form of the f32 so the file doesn't end up with the
+// f64 rounding noise, eg. 56.144974 rather than 56.14497375488281.
+fn float_value(f: f32) -> Value {
+    Value::Float(f.to_string().parse().unwrap())
+}
@@ -0,0 +1,70 @@
+use std::ops::Range;
+
+use math::Vec2;
+use world::SectorIndex;
+use texture::TextureId;
+
+
+// A camera facing image standing on the floor of its sector, `width` and `height`
+// are in world units.
+#[derive(Clone,Copy)]
+pub struct Sprite {
+    pub pos: Vec2,
+    pub sector: SectorIndex,
+    pub texture: TextureId,
+    pub width: f32,
+    pub height: f32,
+}
+
+// Records, for every screen column, the window of rows which is still open beyond each
+// wall the ray passed through. Sprites are clipped against the window which was open at
+// their own depth, so anything drawn in front of them by the ray caster stays on top.
+pub struct DepthClip {
+    _first: usize,
+    _columns: Vec<Vec<(f32, isize, isize)>>,
+}
+
+
+impl Sprite {
+    pub fn new(pos: Vec2, sector: SectorIndex, texture: TextureId, width: f32, height: f32) -> Sprite {
+        Sprite {
+            pos: pos,
+            sector: sector,
+            texture: texture,
+            width: width,
+            height: height,
+        }
+    }
+}
+
+impl DepthClip {
+    pub fn new(columns: Range<usize>, height: usize) -> DepthClip {
+        DepthClip {
+            _first: columns.start,
+            _columns: vec![vec![(0.0, 0, height as isize)]; columns.len()],
+        }
+    }
+
+    // Starts over for another frame, keeping the memory of each column's list.
+    pub fn reset(&mut self, columns: Range<usize>, height: usize) {
+        self._first = columns.start;
+        self._columns.resize_with(columns.len(), Vec::new);
+        for column in self._columns.iter_mut() {
+            column.clear();
+            column.push((0.0, 0, height as isize));
+        }
+    }
+
+    // Rows `top..bottom` of column `x` remain visible beyond `depth`.
+    pub fn push(&mut self, x: usize, depth: f32, top: isize, bottom: isize) {
+        self._columns[x - self._first].push((depth, top, bottom));
+    }
+
+    pub fn window(&self, x: usize, depth: f32) -> (isize, isize) {
+        self._columns[x - self._first].iter()
+            .take_while(|&&(d, _, _)| d < depth)
+            .last()
+            .map(|&(_, top, bottom)| (top, bottom))
+            .unwrap_or((0, 0))
+    }
+}
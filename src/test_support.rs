@@ -0,0 +1,30 @@
+// Fixtures shared by the test modules.
+
+use math::Vec2;
+use texture::TextureId;
+use world::SectorInfo;
+
+
+pub const EPSILON: f32 = 1.0e-3;
+
+
+// A fully lit sector without fog, using the first texture throughout.
+pub fn info(floor_elev: f32, ceiling_elev: f32) -> SectorInfo {
+    SectorInfo {
+        floor_elev: floor_elev,
+        ceiling_elev: ceiling_elev,
+        floor_tex: TextureId(0),
+        ceiling_tex: TextureId(0),
+        light: 1.0,
+        fog: None,
+    }
+}
+
+// The corners of an axis aligned rectangle, for `WorldBuilder::add_sector`.
+pub fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<Vec2> {
+    vec![Vec2::new(x0, y0), Vec2::new(x1, y0), Vec2::new(x1, y1), Vec2::new(x0, y1)]
+}
+
+pub fn assert_near(actual: Vec2, x: f32, y: f32) {
+    assert!(actual.is_near(Vec2::new(x, y), EPSILON), "expected ({}, {}), got ({}, {})", x, y, actual.x, actual.y);
+}
@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::BlendMode;
+use sdl2::surface::Surface;
+use sdl2_image::LoadSurface;
+
+use resources::Resources;
+
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct TextureId(pub usize);
+
+// Pixels are stored in the same ARGB8888 byte order as the framebuffer.
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+pub struct TextureRegistry {
+    _textures: Vec<Texture>,
+}
+
+
+impl Texture {
+    pub fn load(path: &Path) -> Result<Texture, String> {
+        let mut image: Surface = LoadSurface::from_file(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        image.set_blend_mode(BlendMode::None)?;
+
+        let (w, h) = (image.width() as usize, image.height() as usize);
+        let mut argb = Surface::new(w as u32, h as u32, PixelFormatEnum::ARGB8888)?;
+        image.blit(None, &mut argb, None)?;
+
+        let pitch = argb.pitch() as usize;
+        let pixels = argb.with_lock(|buffer| {
+            (0..h).flat_map(|y| buffer[y*pitch .. y*pitch + 4*w].iter().cloned()).collect()
+        });
+
+        Ok(Texture {
+            width: w,
+            height: h,
+            pixels: pixels,
+        })
+    }
+
+    // Samples with wrapping, `u` and `v` are in texels.
+    pub fn sample(&self, u: f32, v: f32) -> (u8,u8,u8) {
+        let (r, g, b, _) = self.sample_rgba(u, v);
+        (r, g, b)
+    }
+
+    pub fn sample_rgba(&self, u: f32, v: f32) -> (u8,u8,u8,u8) {
+        let x = wrap(u.floor() as isize, self.width);
+        let y = wrap(v.floor() as isize, self.height);
+        let i = 4*(self.width*y + x);
+        (self.pixels[i+2], self.pixels[i+1], self.pixels[i+0], self.pixels[i+3])
+    }
+}
+
+impl TextureRegistry {
+    // Loads `<dir>/<name>.png` for every name, so that `TextureId(i)` refers to `names[i]`.
+    pub fn load(dir: &Path, names: &[String]) -> Result<TextureRegistry, String> {
+        TextureRegistry::load_each(names, |name| Ok(dir.join(format!("{}.png", name))))
+    }
+
+    // As `load`, but finds each `textures/<name>.png` wherever the resources provide it.
+    pub fn locate(resources: &Resources, names: &[String]) -> Result<TextureRegistry, String> {
+        TextureRegistry::load_each(names, |name| resources.find(&format!("textures/{}.png", name)))
+    }
+
+    fn load_each<F: Fn(&str) -> Result<PathBuf, String>>(names: &[String], path: F) -> Result<TextureRegistry, String> {
+        let mut textures = vec![];
+        for name in names.iter() {
+            textures.push(Texture::load(&path(name)?)?);
+        }
+        Ok(TextureRegistry {
+            _textures: textures,
+        })
+    }
+
+    pub fn get(&self, id: TextureId) -> &Texture {
+        let TextureId(index) = id;
+        &self._textures[index]
+    }
+}
+
+
+fn wrap(i: isize, size: usize) -> usize {
+    let size = size as isize;
+    (((i % size) + size) % size) as usize
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+
+use world::{World, SectorIndex, WallIndex};
+use texture::TextureId;
+use mover::Trigger;
+
+
+const EPSILON: f32 = 1.0e-3;
+
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Diagnostic {
+    PortalOutOfRange { sector: SectorIndex, wall: WallIndex, target: (SectorIndex,WallIndex) },
+    AsymmetricPortal { sector: SectorIndex, wall: WallIndex, target: (SectorIndex,WallIndex) },
+    PortalMismatch   { sector: SectorIndex, wall: WallIndex, target: (SectorIndex,WallIndex) },
+    UnclosedSector   { sector: SectorIndex, wall: WallIndex },
+    DegenerateWall   { sector: SectorIndex, wall: WallIndex },
+    MissingTexture   { sector: SectorIndex, wall: Option<WallIndex>, texture: TextureId },
+    SpriteOutsideSector  { sprite: usize, sector: SectorIndex },
+    MissingSpriteTexture { sprite: usize, texture: TextureId },
+    MoverOutOfRange      { mover: usize, sector: SectorIndex },
+    TriggerOutOfRange    { mover: usize, target: (SectorIndex,WallIndex) },
+}
+
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnostic::PortalOutOfRange { sector, wall, target } =>
+                write!(f, "sector {} wall {}: portal to missing sector {} wall {}", sector.0, wall.0, (target.0).0, (target.1).0),
+            Diagnostic::AsymmetricPortal { sector, wall, target } =>
+                write!(f, "sector {} wall {}: portal to sector {} wall {} does not lead back", sector.0, wall.0, (target.0).0, (target.1).0),
+            Diagnostic::PortalMismatch { sector, wall, target } =>
+                write!(f, "sector {} wall {}: segment does not coincide with sector {} wall {}", sector.0, wall.0, (target.0).0, (target.1).0),
+            Diagnostic::UnclosedSector { sector, wall } =>
+                write!(f, "sector {} wall {}: wall is not part of a closed loop", sector.0, wall.0),
+            Diagnostic::DegenerateWall { sector, wall } =>
+                write!(f, "sector {} wall {}: wall has zero length", sector.0, wall.0),
+            Diagnostic::MissingTexture { sector, wall: Some(wall), texture } =>
+                write!(f, "sector {} wall {}: unknown texture {}", sector.0, wall.0, texture.0),
+            Diagnostic::MissingTexture { sector, wall: None, texture } =>
+                write!(f, "sector {}: unknown texture {}", sector.0, texture.0),
+            Diagnostic::SpriteOutsideSector { sprite, sector } =>
+                write!(f, "sprite {}: not inside sector {}", sprite, sector.0),
+            Diagnostic::MissingSpriteTexture { sprite, texture } =>
+                write!(f, "sprite {}: unknown texture {}", sprite, texture.0),
+            Diagnostic::MoverOutOfRange { mover, sector } =>
+                write!(f, "mover {}: missing sector {}", mover, sector.0),
+            Diagnostic::TriggerOutOfRange { mover, target } =>
+                write!(f, "mover {}: trigger on missing sector {} wall {}", mover, (target.0).0, (target.1).0),
+        }
+    }
+}
+
+
+impl World {
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let sectors = self.get_sectors();
+        let texture_count = self.get_texture_names().len();
+        let mut result = vec![];
+
+        for (sec_index, sector) in sectors.iter().enumerate() {
+            let sec = SectorIndex(sec_index);
+
+            for &texture in [sector.info.floor_tex, sector.info.ceiling_tex].iter() {
+                if texture.0 >= texture_count {
+                    result.push(Diagnostic::MissingTexture { sector: sec, wall: None, texture: texture });
+                }
+            }
+
+            for (wall_index, wall) in sector.walls.iter().enumerate() {
+                let w = WallIndex(wall_index);
+
+                for &texture in [wall.upper.texture, wall.lower.texture, wall.middle.texture].iter() {
+                    if texture.0 >= texture_count {
+                        result.push(Diagnostic::MissingTexture { sector: sec, wall: Some(w), texture: texture });
+                    }
+                }
+
+                if wall.seg.get_length() < EPSILON {
+                    result.push(Diagnostic::DegenerateWall { sector: sec, wall: w });
+                }
+
+                let others = || sector.walls.iter().enumerate()
+                    .filter(move |&(i, _)| i != wall_index)
+                    .map(|(_, other)| other);
+
+                let has_next = others().any(|other| other.seg.a.is_near(wall.seg.b, EPSILON));
+                let has_prev = others().any(|other| other.seg.b.is_near(wall.seg.a, EPSILON));
+
+                if !has_next || !has_prev {
+                    result.push(Diagnostic::UnclosedSector { sector: sec, wall: w });
+                }
+
+                if let Some((SectorIndex(target_sec), WallIndex(target_wall))) = wall.portal {
+                    let target = (SectorIndex(target_sec), WallIndex(target_wall));
+
+                    if target_sec >= sectors.len() || target_wall >= sectors[target_sec].walls.len() {
+                        result.push(Diagnostic::PortalOutOfRange { sector: sec, wall: w, target: target });
+                        continue;
+                    }
+
+                    let other = &sectors[target_sec].walls[target_wall];
+
+                    if other.portal != Some((sec, w)) {
+                        result.push(Diagnostic::AsymmetricPortal { sector: sec, wall: w, target: target });
+                    }
+
+                    let forward = wall.seg.a.is_near(other.seg.a, EPSILON) && wall.seg.b.is_near(other.seg.b, EPSILON);
+                    let reverse = wall.seg.a.is_near(other.seg.b, EPSILON) && wall.seg.b.is_near(other.seg.a, EPSILON);
+
+                    if !forward && !reverse {
+                        result.push(Diagnostic::PortalMismatch { sector: sec, wall: w, target: target });
+                    }
+                }
+            }
+        }
+
+        for (sprite_index, sprite) in self.get_sprites().iter().enumerate() {
+            let SectorIndex(sec_index) = sprite.sector;
+
+            if sec_index >= sectors.len() || !sectors[sec_index].contains(sprite.pos) {
+                result.push(Diagnostic::SpriteOutsideSector { sprite: sprite_index, sector: sprite.sector });
+            }
+            if sprite.texture.0 >= texture_count {
+                result.push(Diagnostic::MissingSpriteTexture { sprite: sprite_index, texture: sprite.texture });
+            }
+        }
+
+        for (mover_index, mover) in self.get_movers().iter().enumerate() {
+            if mover.sector.0 >= sectors.len() {
+                result.push(Diagnostic::MoverOutOfRange { mover: mover_index, sector: mover.sector });
+            }
+            if let Trigger::Walk(SectorIndex(target_sec), WallIndex(target_wall)) = mover.trigger {
+                if target_sec >= sectors.len() || target_wall >= sectors[target_sec].walls.len() {
+                    result.push(Diagnostic::TriggerOutOfRange { mover: mover_index, target: (SectorIndex(target_sec), WallIndex(target_wall)) });
+                }
+            }
+        }
+
+        result
+    }
+}
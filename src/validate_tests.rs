@@ -0,0 +1,92 @@
+// Tests for `World::validate`, one small broken world per kind of map diagnostic.
+
+use texture::TextureId;
+use validate::Diagnostic;
+use test_support::info;
+use world::{self, World, Sector, SectorIndex, Wall, WallIndex};
+
+
+const BRICK: TextureId = TextureId(0);
+
+
+// Walls of the 10 unit square with its first corner at (x0, 0).
+fn square(x0: f32) -> Vec<Wall> {
+    vec![
+        Wall::new(x0,        0.0, x0 + 10.0,  0.0, BRICK, None),
+        Wall::new(x0 + 10.0, 0.0, x0 + 10.0, 10.0, BRICK, None),
+        Wall::new(x0 + 10.0, 10.0, x0,       10.0, BRICK, None),
+        Wall::new(x0,        10.0, x0,        0.0, BRICK, None),
+    ]
+}
+
+fn world(sectors: Vec<Vec<Wall>>) -> World {
+    World::new(sectors.into_iter().map(|walls| Sector { info: info(0.0, 20.0), walls: walls }).collect(), vec!["brick".to_string()])
+}
+
+fn portal(sector: usize, wall: usize) -> Option<(SectorIndex,WallIndex)> {
+    Some((SectorIndex(sector), WallIndex(wall)))
+}
+
+
+#[test]
+fn temp_world_is_valid() {
+    assert_eq!(world::temp().validate(), vec![]);
+}
+
+#[test]
+fn portal_out_of_range() {
+    let mut walls = square(0.0);
+    walls[1].portal = portal(5, 0);
+
+    assert_eq!(world(vec![walls]).validate(), vec![
+        Diagnostic::PortalOutOfRange { sector: SectorIndex(0), wall: WallIndex(1), target: (SectorIndex(5), WallIndex(0)) },
+    ]);
+}
+
+#[test]
+fn asymmetric_portal() {
+    // The two squares share an edge, but only the left one has a portal through it.
+    let mut left = square(0.0);
+    left[1].portal = portal(1, 3);
+
+    assert_eq!(world(vec![left, square(10.0)]).validate(), vec![
+        Diagnostic::AsymmetricPortal { sector: SectorIndex(0), wall: WallIndex(1), target: (SectorIndex(1), WallIndex(3)) },
+    ]);
+}
+
+#[test]
+fn portal_mismatch() {
+    // Linked both ways, but the right square's wall is its east edge rather than the shared one.
+    let mut left = square(0.0);
+    let mut right = square(10.0);
+    left[1].portal = portal(1, 1);
+    right[1].portal = portal(0, 1);
+
+    assert_eq!(world(vec![left, right]).validate(), vec![
+        Diagnostic::PortalMismatch { sector: SectorIndex(0), wall: WallIndex(1), target: (SectorIndex(1), WallIndex(1)) },
+        Diagnostic::PortalMismatch { sector: SectorIndex(1), wall: WallIndex(1), target: (SectorIndex(0), WallIndex(1)) },
+    ]);
+}
+
+#[test]
+fn unclosed_sector() {
+    // Without the west wall the loop ends at the first and last walls.
+    let mut walls = square(0.0);
+    walls.pop();
+
+    assert_eq!(world(vec![walls]).validate(), vec![
+        Diagnostic::UnclosedSector { sector: SectorIndex(0), wall: WallIndex(0) },
+        Diagnostic::UnclosedSector { sector: SectorIndex(0), wall: WallIndex(2) },
+    ]);
+}
+
+#[test]
+fn degenerate_wall() {
+    // A zero length wall in the corner still closes the loop, so only its length is reported.
+    let mut walls = square(0.0);
+    walls.insert(1, Wall::new(10.0, 0.0, 10.0, 0.0, BRICK, None));
+
+    assert_eq!(world(vec![walls]).validate(), vec![
+        Diagnostic::DegenerateWall { sector: SectorIndex(0), wall: WallIndex(1) },
+    ]);
+}
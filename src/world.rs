@@ -1,64 +1,215 @@
 use math::{LineSeg, Vec2};
+use texture::TextureId;
+use sprite::Sprite;
+use mover::Mover;
 
 
+#[derive(Clone)]
 pub struct World {
     _sectors: Vec<Sector>,
+    _textures: Vec<String>,
+    _sprites: Vec<Sprite>,
+    _movers: Vec<Mover>,
 }
 
+#[derive(Clone)]
 pub struct Sector {
     pub info: SectorInfo,
     pub walls: Vec<Wall>,
 }
 
+// `light` scales the brightness of everything in the sector, 1.0 being fully lit.
 #[derive(Clone,Copy)]
 pub struct SectorInfo {
     pub floor_elev: f32,
     pub ceiling_elev: f32,
+    pub floor_tex: TextureId,
+    pub ceiling_tex: TextureId,
+    pub light: f32,
+    pub fog: Option<Fog>,
 }
 
-#[derive(Clone,Copy,PartialEq)]
+// Surfaces fade towards `color` with distance instead of towards black.
+#[derive(Clone,Copy)]
+pub struct Fog {
+    pub color: (u8,u8,u8),
+    pub density: f32,
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
 pub struct SectorIndex(pub usize);
 
-#[derive(Clone,Copy,PartialEq)]
+#[derive(Clone,Copy,PartialEq,Debug)]
 pub struct WallIndex(pub usize);
 
+// Which edge of a wall section the texture is anchored to.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Peg {
+    Top,
+    Bottom,
+}
+
+// Offsets are in texels, a scale of 2.0 draws the texture twice as large.
+#[derive(Clone,Copy)]
+pub struct WallTexture {
+    pub texture: TextureId,
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub scale: f32,
+    pub peg: Peg,
+}
+
+// `lower` and `upper` cover the steps between this sector and the one behind a portal,
+// `middle` covers walls without a portal.
 #[derive(Clone,Copy)]
 pub struct Wall {
     pub seg: LineSeg,
     pub portal: Option<(SectorIndex,WallIndex)>,
+    pub upper: WallTexture,
+    pub lower: WallTexture,
+    pub middle: WallTexture,
+}
+
+// Iterator returned by `World::cast_ray`, which walks portals without allocating.
+pub struct RayCast<'a> {
+    _world: &'a World,
+    _sector: SectorIndex,
+    _source_wall: Option<WallIndex>,
+    _pos: Vec2,
+    _angle: f32,
+    _depth: usize,
+    _done: bool,
+}
+
+// Dimensions of a moving object, `step_height` is the tallest rise it can walk up.
+#[derive(Clone,Copy)]
+pub struct Collider {
+    pub radius: f32,
+    pub height: f32,
+    pub step_height: f32,
 }
 
 #[derive(Clone,Copy)]
 pub struct RayCastResult {
     pub along: f32,
+    pub sector: SectorIndex,
+    pub wall_index: WallIndex,
+    pub wall: Wall,
     pub hit_pos: Vec2,
     pub in_info: SectorInfo,
     pub out_info: Option<SectorInfo>,
 }
 
 
+const SLIDE_ITERATIONS: usize = 3;
+// Guards against portal cycles in malformed maps.
+const MAX_PORTAL_DEPTH: usize = 64;
+const RAY_LENGTH: f32 = 1000.0;
+const RAY_EPSILON: f32 = 1.0e-3;
+const SLIDE_EPSILON: f32 = 1.0e-4;
+
+
 pub static W_ZERO: Wall = Wall {
     seg: LineSeg {
         a: Vec2 { x: 0.0, y: 0.0 },
         b: Vec2 { x: 0.0, y: 0.0 },
     },
-    portal: None
+    portal: None,
+    upper: WallTexture { texture: TextureId(0), offset_x: 0.0, offset_y: 0.0, scale: 1.0, peg: Peg::Bottom },
+    lower: WallTexture { texture: TextureId(0), offset_x: 0.0, offset_y: 0.0, scale: 1.0, peg: Peg::Top },
+    middle: WallTexture { texture: TextureId(0), offset_x: 0.0, offset_y: 0.0, scale: 1.0, peg: Peg::Top },
 };
 
 
+impl WallTexture {
+    pub fn new(texture: TextureId, peg: Peg) -> WallTexture {
+        WallTexture {
+            texture: texture,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            scale: 1.0,
+            peg: peg,
+        }
+    }
+}
+
 impl Wall {
-    pub fn new(x0: f32, y0: f32, x1: f32, y1: f32, portal: Option<(SectorIndex,WallIndex)>) -> Wall {
+    pub fn new(x0: f32, y0: f32, x1: f32, y1: f32, texture: TextureId, portal: Option<(SectorIndex,WallIndex)>) -> Wall {
+        Wall::from_seg(LineSeg::new(x0, y0, x1, y1), texture, portal)
+    }
+
+    // Uses the same texture for every section. The upper section is anchored to its bottom
+    // edge, where it meets the opening below it, and the lower and middle sections to their
+    // top edges.
+    pub fn from_seg(seg: LineSeg, texture: TextureId, portal: Option<(SectorIndex,WallIndex)>) -> Wall {
         Wall {
-            seg: LineSeg::new(x0, y0, x1, y1),
+            seg: seg,
             portal: portal,
+            upper: WallTexture::new(texture, Peg::Bottom),
+            lower: WallTexture::new(texture, Peg::Top),
+            middle: WallTexture::new(texture, Peg::Top),
         }
     }
 }
 
 
+impl Sector {
+    pub fn contains(&self, pos: Vec2) -> bool {
+        let mut inside = false;
+
+        for wall in self.walls.iter() {
+            let LineSeg { a, b } = wall.seg;
+            if (a.y > pos.y) != (b.y > pos.y) && pos.x < a.x + (pos.y - a.y) * (b.x - a.x) / (b.y - a.y) {
+                inside = !inside;
+            }
+        }
+
+        inside
+    }
+}
+
+
 impl World {
-    pub fn get_walls(&self) -> &[Wall] {
-        self._sectors[0].walls.as_slice()
+    pub fn new(sectors: Vec<Sector>, textures: Vec<String>) -> World {
+        World {
+            _sectors: sectors,
+            _textures: textures,
+            _sprites: vec![],
+            _movers: vec![],
+        }
+    }
+
+    pub fn add_sprite(&mut self, sprite: Sprite) {
+        self._sprites.push(sprite);
+    }
+
+    pub fn add_mover(&mut self, mover: Mover) {
+        self._movers.push(mover);
+    }
+
+    pub fn get_sectors(&self) -> &[Sector] {
+        self._sectors.as_slice()
+    }
+
+    pub fn get_texture_names(&self) -> &[String] {
+        self._textures.as_slice()
+    }
+
+    pub fn get_sprites(&self) -> &[Sprite] {
+        self._sprites.as_slice()
+    }
+
+    pub fn get_movers(&self) -> &[Mover] {
+        self._movers.as_slice()
+    }
+
+    pub fn get_sector_info_mut(&mut self, sector: SectorIndex) -> &mut SectorInfo {
+        let SectorIndex(index) = sector;
+        &mut self._sectors[index].info
+    }
+
+    pub fn find_sector(&self, pos: Vec2) -> Option<SectorIndex> {
+        self._sectors.iter().position(|sector| sector.contains(pos)).map(SectorIndex)
     }
 
     pub fn get_elevation(&self, sector: SectorIndex) -> f32 {
@@ -66,112 +217,276 @@ impl World {
         self._sectors[index].info.floor_elev
     }
 
-    pub fn move_object(&self, sector: SectorIndex, old_pos: Vec2, new_pos: Vec2) -> SectorIndex {
+    pub fn get_ceiling_elevation(&self, sector: SectorIndex) -> f32 {
         let SectorIndex(index) = sector;
-        let move_seg = LineSeg { a: old_pos, b: new_pos };
+        self._sectors[index].info.ceiling_elev
+    }
+
+    // Moves an object whose feet are at `feet_elev` from `old_pos` towards `new_pos`.
+    // Movement into a solid wall, or into a portal the object cannot step up or fit
+    // through, is projected along it so the object slides rather than stopping dead.
+    // Returns the sector the object ends up in and its final position.
+    pub fn move_object(&self, sector: SectorIndex, old_pos: Vec2, new_pos: Vec2, feet_elev: f32, collider: &Collider) -> (SectorIndex, Vec2) {
+        let SectorIndex(index) = sector;
+        let radius = collider.radius;
+        let walls = self.nearby_blocking_walls(sector, feet_elev, collider);
+        let mut delta = new_pos - old_pos;
+
+        for _ in 0..SLIDE_ITERATIONS {
+            match walls.clone().find(|wall| blocks_move(wall.seg, old_pos, old_pos + delta, radius)) {
+                Some(wall) => delta = delta.project(wall.seg.b - wall.seg.a),
+                None => break,
+            }
+        }
+
+        let end_pos = if walls.clone().any(|wall| blocks_move(wall.seg, old_pos, old_pos + delta, radius)) {
+            old_pos
+        } else {
+            old_pos + delta
+        };
+
+        let move_seg = LineSeg { a: old_pos, b: end_pos };
 
         let crossed_portal = self._sectors[index].walls.iter()
             .filter_map(|&wall| move_seg.intersects(wall.seg).and(wall.portal))
-            .last();
+            .next_back();
 
         match crossed_portal {
-            Some((new_sector, _)) => new_sector,
-            None => sector,
+            Some((new_sector, _)) => (new_sector, end_pos),
+            None => (sector, end_pos),
         }
     }
 
-    pub fn cast_ray(&self, sector: SectorIndex, pos: Vec2, angle: f32) -> Vec<RayCastResult> {
-        let mut result = vec![];
-        self._cast_ray(sector, None, pos, angle, &mut result);
-        result
+    pub fn can_enter(&self, from: SectorIndex, to: SectorIndex, feet_elev: f32, collider: &Collider) -> bool {
+        let (SectorIndex(from), SectorIndex(to)) = (from, to);
+        let from = &self._sectors[from].info;
+        let to = &self._sectors[to].info;
+
+        let opening = from.ceiling_elev.min(to.ceiling_elev) - feet_elev.max(to.floor_elev);
+
+        to.floor_elev - feet_elev <= collider.step_height
+            && to.ceiling_elev - to.floor_elev >= collider.height
+            && opening >= collider.height
+    }
+
+    // Walls of the sector which cannot be passed, plus the solid walls of its direct
+    // neighbours which an object standing near a portal may already be touching.
+    fn nearby_blocking_walls<'a>(&'a self, sector: SectorIndex, feet_elev: f32, collider: &'a Collider) -> impl Iterator<Item = &'a Wall> + Clone + 'a {
+        let SectorIndex(index) = sector;
+        let walls = &self._sectors[index].walls;
+
+        let own = walls.iter()
+            .filter(move |wall| match wall.portal {
+                Some((next, _)) => !self.can_enter(sector, next, feet_elev, collider),
+                None => true,
+            });
+
+        let neighbours = walls.iter()
+            .filter_map(|wall| wall.portal)
+            .flat_map(move |(SectorIndex(neighbour), _)| self._sectors[neighbour].walls.iter())
+            .filter(|wall| wall.portal.is_none());
+
+        own.chain(neighbours)
     }
 
-    fn _cast_ray(&self, sector: SectorIndex, source_wall: Option<WallIndex>, pos: Vec2, angle: f32, results: &mut Vec<RayCastResult>) {
+    // Walks the ray outwards through portals, yielding every wall it hits in order.
+    pub fn cast_ray<'a>(&'a self, sector: SectorIndex, pos: Vec2, angle: f32) -> RayCast<'a> {
+        RayCast {
+            _world: self,
+            _sector: sector,
+            _source_wall: None,
+            _pos: pos,
+            _angle: angle,
+            _depth: 0,
+            _done: false,
+        }
+    }
+}
+
+
+impl<'a> Iterator for RayCast<'a> {
+    type Item = RayCastResult;
+
+    fn next(&mut self) -> Option<RayCastResult> {
+        if self._done || self._depth >= MAX_PORTAL_DEPTH {
+            return None;
+        }
+
+        let pos = self._pos;
+        let source_wall = self._source_wall;
         let ray = LineSeg::new(
             pos.x, pos.y,
-            pos.x + 1000.0*angle.sin(),
-            pos.y - 1000.0*angle.cos()
+            pos.x + RAY_LENGTH*self._angle.sin(),
+            pos.y - RAY_LENGTH*self._angle.cos()
         );
 
+        let SectorIndex(sec_index) = self._sector;
+        let sectors = &self._world._sectors;
 
+        let mut closest_wall: Option<(usize, &Wall, f32, f32)> = None;
 
-        let SectorIndex(sec_index) = sector;
+        for (i, wall) in sectors[sec_index].walls.iter().enumerate() {
+            if Some(WallIndex(i)) == source_wall { continue; }
 
-        let closest_wall = self._sectors[sec_index].walls.iter().enumerate()
-            .filter(|&(i, _)| source_wall.is_none() || WallIndex(i) != source_wall.unwrap())
-            .filter_map(|(_, wall)| ray.intersects(wall.seg).map(|t| (wall, t)))
-            .min_by_key(|&(&wall, t)| {
-                let d2 = (pos - wall.seg.at(t)).get_length_sqr();
-                if d2 < 0.01 { 1000000 } else { (d2 * 100.0) as i32 }
-                    // TODO get closest wall without the integer distance conversion.
-                    // Need to convert to integer here to satisfy Ord constraint, annoyingly.
-            });
+            if let Some((ray_t, t)) = ray.intersect_params(wall.seg) {
+                let dist = ray_t * RAY_LENGTH;
+                let nearer = match closest_wall {
+                    Some((_, best, best_dist, _)) => is_nearer_hit(wall, dist, best, best_dist, source_wall.is_some()),
+                    None => true,
+                };
+                if nearer {
+                    closest_wall = Some((i, wall, dist, t));
+                }
+            }
+        }
+
+        let (wall_index, wall, t) = match closest_wall {
+            Some((i, wall, _, t)) => (i, wall, t),
+            None => {
+                self._done = true;
+                return None;
+            }
+        };
 
-        if closest_wall.is_none() { return; }
+        let hit_pos = wall.seg.at(t);
 
-        let (wall, t) = closest_wall.unwrap();
+        match wall.portal {
+            Some((next_sector, next_wall)) => {
+                self._sector = next_sector;
+                self._source_wall = Some(next_wall);
+                self._pos = hit_pos;
+                self._depth += 1;
+            }
+            None => self._done = true,
+        }
 
-        results.push(RayCastResult {
+        Some(RayCastResult {
             along: wall.seg.get_length()*t,
-            hit_pos: wall.seg.at(t),
-            in_info: self._sectors[sec_index].info,
-            out_info: wall.portal.map(|(SectorIndex(sec), _)| self._sectors[sec].info),
-        });
-
-        // TODO refactor recursion in to loop
-        if let Some((next_sector, next_wall)) = wall.portal {
-            self._cast_ray(next_sector, Some(next_wall), wall.seg.at(t), angle, results);
+            sector: SectorIndex(sec_index),
+            wall_index: WallIndex(wall_index),
+            wall: *wall,
+            hit_pos: hit_pos,
+            in_info: sectors[sec_index].info,
+            out_info: wall.portal.map(|(SectorIndex(sec), _)| sectors[sec].info),
+        })
+    }
+}
+
+
+// Orders hits by distance along the ray. After passing through a portal the ray starts on
+// the portal's end points whenever it goes through a vertex, and walls meeting there are
+// hit at distance zero. Those hits only count if nothing else is hit, so the ray can carry
+// on through a vertex shared by several portals. Hits within RAY_EPSILON of each other are
+// treated as ties and go to solid walls, so rays into a corner never leak through a portal.
+fn is_nearer_hit(wall: &Wall, dist: f32, best: &Wall, best_dist: f32, from_portal: bool) -> bool {
+    if from_portal {
+        let at_origin = dist < RAY_EPSILON;
+        let best_at_origin = best_dist < RAY_EPSILON;
+        if at_origin != best_at_origin {
+            return best_at_origin;
         }
     }
+
+    if (dist - best_dist).abs() < RAY_EPSILON {
+        let solid = wall.portal.is_none();
+        let best_solid = best.portal.is_none();
+        if solid != best_solid {
+            return solid;
+        }
+    }
+
+    dist < best_dist
 }
 
+// A move is blocked if it passes through the wall or brings the object closer to it
+// than `radius`. Moving parallel to or away from a wall that is already too close is fine.
+fn blocks_move(seg: LineSeg, from: Vec2, to: Vec2, radius: f32) -> bool {
+    let from_dist = (from - seg.closest_point(from)).get_length();
+    let to_dist = (to - seg.closest_point(to)).get_length();
+
+    (to_dist < radius && to_dist < from_dist - SLIDE_EPSILON)
+        || LineSeg { a: from, b: to }.intersects(seg).is_some()
+}
 
 
 pub fn temp() -> World {
+    const BRICK: TextureId = TextureId(0);
+    const METAL: TextureId = TextureId(1);
+    const STONE: TextureId = TextureId(2);
+    const PANEL: TextureId = TextureId(3);
+    const BARREL: TextureId = TextureId(4);
+
     World {
+        _textures: vec![
+            "brick".to_string(),
+            "metal".to_string(),
+            "stone".to_string(),
+            "panel".to_string(),
+            "barrel".to_string(),
+        ],
+        _sprites: vec![
+            Sprite::new(Vec2::new(  5.0, -30.0), SectorIndex(0), BARREL, 3.0, 4.0),
+            Sprite::new(Vec2::new(-18.0, -18.0), SectorIndex(1), BARREL, 3.0, 4.0),
+            Sprite::new(Vec2::new( 17.0,  17.0), SectorIndex(2), BARREL, 3.0, 4.0),
+        ],
+        _movers: vec![
+            Mover::lift(SectorIndex(1), 7.0, 0.0),
+        ],
         _sectors: vec![
             Sector {
                 info: SectorInfo {
                     floor_elev: 0.0,
                     ceiling_elev: 20.0,
+                    floor_tex: STONE,
+                    ceiling_tex: PANEL,
+                    light: 1.0,
+                    fog: None,
                 },
                 walls: vec![
-                    Wall::new(-40.0, -40.0,  40.0, -40.0, None),
-                    Wall::new( 40.0, -40.0,  40.0,  40.0, None),
-                    Wall::new( 40.0,  40.0, -40.0,  40.0, None),
-                    Wall::new(-40.0,  40.0, -40.0, -40.0, None),
-                    Wall::new(-25.0, -25.0, -10.0, -25.0, Some((SectorIndex(1),WallIndex(0)))),
-                    Wall::new(-10.0, -25.0, -10.0,  -1.0, Some((SectorIndex(1),WallIndex(1)))),
-                    Wall::new(-10.0,  -1.0, -25.0, -10.0, Some((SectorIndex(1),WallIndex(2)))),
-                    Wall::new(-25.0, -10.0, -25.0, -25.0, Some((SectorIndex(1),WallIndex(3)))),
-                    Wall::new( 25.0,  25.0,  10.0,  25.0, Some((SectorIndex(2),WallIndex(0)))),
-                    Wall::new( 10.0,  25.0,  10.0,  10.0, Some((SectorIndex(2),WallIndex(1)))),
-                    Wall::new( 10.0,  10.0,  25.0,  10.0, Some((SectorIndex(2),WallIndex(2)))),
-                    Wall::new( 25.0,  10.0,  25.0,  25.0, Some((SectorIndex(2),WallIndex(3))))
+                    Wall::new(-40.0, -40.0,  40.0, -40.0, BRICK, None),
+                    Wall::new( 40.0, -40.0,  40.0,  40.0, BRICK, None),
+                    Wall::new( 40.0,  40.0, -40.0,  40.0, BRICK, None),
+                    Wall::new(-40.0,  40.0, -40.0, -40.0, BRICK, None),
+                    Wall::new(-25.0, -25.0, -10.0, -25.0, METAL, Some((SectorIndex(1),WallIndex(0)))),
+                    Wall::new(-10.0, -25.0, -10.0,  -1.0, METAL, Some((SectorIndex(1),WallIndex(1)))),
+                    Wall::new(-10.0,  -1.0, -25.0, -10.0, METAL, Some((SectorIndex(1),WallIndex(2)))),
+                    Wall::new(-25.0, -10.0, -25.0, -25.0, METAL, Some((SectorIndex(1),WallIndex(3)))),
+                    Wall::new( 25.0,  25.0,  10.0,  25.0, METAL, Some((SectorIndex(2),WallIndex(0)))),
+                    Wall::new( 10.0,  25.0,  10.0,  10.0, METAL, Some((SectorIndex(2),WallIndex(1)))),
+                    Wall::new( 10.0,  10.0,  25.0,  10.0, METAL, Some((SectorIndex(2),WallIndex(2)))),
+                    Wall::new( 25.0,  10.0,  25.0,  25.0, METAL, Some((SectorIndex(2),WallIndex(3))))
                 ]
             },
             Sector {
                 info: SectorInfo {
                     floor_elev: 7.0,
                     ceiling_elev: 23.0,
+                    floor_tex: STONE,
+                    ceiling_tex: PANEL,
+                    light: 1.0,
+                    fog: Some(Fog { color: (0x50, 0x58, 0x68), density: 0.04 }),
                 },
                 walls: vec![
-                    Wall::new(-25.0, -25.0, -10.0, -25.0, Some((SectorIndex(0),WallIndex(4)))),
-                    Wall::new(-10.0, -25.0, -10.0,  -1.0, Some((SectorIndex(0),WallIndex(5)))),
-                    Wall::new(-10.0,  -1.0, -25.0, -10.0, Some((SectorIndex(0),WallIndex(6)))),
-                    Wall::new(-25.0, -10.0, -25.0, -25.0, Some((SectorIndex(0),WallIndex(7)))),
+                    Wall::new(-25.0, -25.0, -10.0, -25.0, METAL, Some((SectorIndex(0),WallIndex(4)))),
+                    Wall::new(-10.0, -25.0, -10.0,  -1.0, METAL, Some((SectorIndex(0),WallIndex(5)))),
+                    Wall::new(-10.0,  -1.0, -25.0, -10.0, METAL, Some((SectorIndex(0),WallIndex(6)))),
+                    Wall::new(-25.0, -10.0, -25.0, -25.0, METAL, Some((SectorIndex(0),WallIndex(7)))),
                 ]
             },
             Sector {
                 info: SectorInfo {
                     floor_elev: -2.0,
                     ceiling_elev: 10.0,
+                    floor_tex: STONE,
+                    ceiling_tex: PANEL,
+                    light: 0.5,
+                    fog: None,
                 },
                 walls: vec![
-                    Wall::new( 25.0,  25.0,  10.0,  25.0, Some((SectorIndex(0),WallIndex(8)))),
-                    Wall::new( 10.0,  25.0,  10.0,  10.0, Some((SectorIndex(0),WallIndex(9)))),
-                    Wall::new( 10.0,  10.0,  25.0,  10.0, Some((SectorIndex(0),WallIndex(10)))),
-                    Wall::new( 25.0,  10.0,  25.0,  25.0, Some((SectorIndex(0),WallIndex(11))))
+                    Wall::new( 25.0,  25.0,  10.0,  25.0, METAL, Some((SectorIndex(0),WallIndex(8)))),
+                    Wall::new( 10.0,  25.0,  10.0,  10.0, METAL, Some((SectorIndex(0),WallIndex(9)))),
+                    Wall::new( 10.0,  10.0,  25.0,  10.0, METAL, Some((SectorIndex(0),WallIndex(10)))),
+                    Wall::new( 25.0,  10.0,  25.0,  25.0, METAL, Some((SectorIndex(0),WallIndex(11))))
                 ]
             }
         ]